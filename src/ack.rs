@@ -0,0 +1,198 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Echo Collector Acknowledgements
+
+use {
+    getset::Getters,
+    serde_derive::{Deserialize, Serialize},
+    std::collections::BTreeSet,
+    uuid::Uuid,
+};
+
+/// A per-event acknowledgement returned by the Echo collector
+#[derive(Clone, Debug, Default, Deserialize, Eq, Getters, PartialEq, Serialize)]
+pub struct CollectorAck {
+    /// The collector assigned id for the event
+    #[get = "pub"]
+    id: String,
+}
+
+/// The batch acknowledgement returned by the Echo collector, i.e.
+/// `{"accepted":2,"rejected":[{"index":1,"reason":"invalid routing key"}]}`
+#[derive(Clone, Debug, Default, Deserialize, Eq, Getters, PartialEq, Serialize)]
+pub struct BatchAck {
+    /// The number of events accepted
+    #[get = "pub"]
+    #[serde(default)]
+    accepted: usize,
+    /// The rejected events
+    #[get = "pub"]
+    #[serde(default)]
+    rejected: Vec<RejectedEvent>,
+    /// The `Idempotency-Key` sent with the acknowledged request, if any.  This isn't
+    /// part of the collector response.
+    #[get = "pub"]
+    #[serde(skip)]
+    idempotency_key: Option<String>,
+    /// The HTTP status code of the collector response, i.e. `202 Accepted` for queued
+    /// events vs `200 OK` for synchronously processed events.  This isn't part of the
+    /// collector response body.
+    #[get = "pub"]
+    #[serde(skip)]
+    status: Option<u16>,
+    /// The per-event collector acks, keyed by the correlation id of the event sent, when
+    /// the collector responds with an ordered array of per-event acks, i.e.
+    /// `[{"id":"a"},{"id":"b"}]`.  This isn't part of a batch acknowledgement body.
+    #[get = "pub"]
+    #[serde(skip)]
+    events: Vec<(Uuid, CollectorAck)>,
+}
+
+impl BatchAck {
+    /// An acknowledgement of `accepted` events from an array of per-event acks,
+    /// correlated with the events sent
+    pub(crate) fn with_events(accepted: usize, events: Vec<(Uuid, CollectorAck)>) -> Self {
+        Self {
+            accepted,
+            events,
+            ..Self::default()
+        }
+    }
+
+    /// Record the `Idempotency-Key` sent with the acknowledged request
+    pub(crate) fn set_idempotency_key(&mut self, key: Option<String>) {
+        self.idempotency_key = key;
+    }
+
+    /// Record the HTTP status code of the collector response
+    pub(crate) fn set_status(&mut self, status: u16) {
+        self.status = Some(status);
+    }
+
+    /// Map the batch indices of the rejected events to their index in the payload, given
+    /// the payload index of each event in the batch.  Indices outside the batch are
+    /// dropped.
+    pub(crate) fn map_indices(&mut self, origins: &[usize]) {
+        self.rejected = self
+            .rejected
+            .drain(..)
+            .filter_map(|mut rejected| {
+                rejected.index = *origins.get(rejected.index)?;
+                Some(rejected)
+            })
+            .collect();
+    }
+
+    /// Select the rejected items, in batch order.  Indices outside the batch, and
+    /// repeated indices, are ignored.
+    pub(crate) fn select<T>(&self, batch: &[T]) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.rejected
+            .iter()
+            .map(|rejected| rejected.index)
+            .collect::<BTreeSet<usize>>()
+            .into_iter()
+            .filter_map(|index| batch.get(index).cloned())
+            .collect()
+    }
+}
+
+/// A collector response rejecting some of the events in a batch, i.e.
+/// `{"rejected":[{"index":1,"reason":"invalid routing key"}]}`.  Returned by a send, the
+/// indices are those of the payload events, before any `Spawner` filtering.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BatchRejection {
+    /// The acknowledgement carrying the rejections
+    #[serde(flatten)]
+    ack: BatchAck,
+}
+
+impl BatchRejection {
+    /// The rejected events
+    #[must_use]
+    pub fn rejected(&self) -> &Vec<RejectedEvent> {
+        &self.ack.rejected
+    }
+
+    /// The full acknowledgement, including the accepted count and response status
+    #[must_use]
+    pub fn ack(&self) -> &BatchAck {
+        &self.ack
+    }
+
+    /// Map the batch indices of the rejected events to their index in the payload
+    pub(crate) fn map_indices(&mut self, origins: &[usize]) {
+        self.ack.map_indices(origins);
+    }
+
+    /// Select the rejected items, in batch order.  Indices outside the batch, and
+    /// repeated indices, are ignored.
+    pub(crate) fn select<T>(&self, batch: &[T]) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.ack.select(batch)
+    }
+}
+
+impl From<BatchAck> for BatchRejection {
+    fn from(ack: BatchAck) -> Self {
+        Self { ack }
+    }
+}
+
+/// A single event rejected by the collector
+#[derive(Clone, Debug, Default, Deserialize, Eq, Getters, PartialEq, Serialize)]
+pub struct RejectedEvent {
+    /// The index of the event in the batch
+    #[get = "pub"]
+    index: usize,
+    /// Why the collector rejected the event
+    #[get = "pub"]
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{BatchAck, BatchRejection},
+        crate::error::Result,
+    };
+
+    #[test]
+    fn select() -> Result<()> {
+        let body =
+            r#"{"rejected":[{"index":3,"reason":"bad"},{"index":1},{"index":3},{"index":7}]}"#;
+        let rejection: BatchRejection = serde_json::from_str(body)?;
+        assert_eq!(rejection.rejected().len(), 4);
+        assert_eq!(rejection.rejected()[0].reason(), &Some("bad".to_string()));
+        assert_eq!(rejection.select(&["a", "b", "c", "d"]), vec!["b", "d"]);
+        Ok(())
+    }
+
+    #[test]
+    fn map_indices() -> Result<()> {
+        let body = r#"{"rejected":[{"index":0},{"index":1},{"index":5}]}"#;
+        let mut rejection: BatchRejection = serde_json::from_str(body)?;
+        rejection.map_indices(&[1, 3]);
+        assert_eq!(rejection.select(&["a", "b", "c", "d"]), vec!["b", "d"]);
+        Ok(())
+    }
+
+    #[test]
+    fn accepted() -> Result<()> {
+        let ack: BatchAck = serde_json::from_str(r#"{"accepted":3}"#)?;
+        assert_eq!(ack.accepted(), &3);
+        assert!(ack.rejected().is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Collector Authentication
+
+use {
+    crate::error::ErrKind,
+    std::{
+        fmt, fs,
+        io::{self, ErrorKind},
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// A bearer token read from a file that is rotated externally (i.e. by a sidecar).
+///
+/// The token is re-read whenever the cached copy is older than the configured TTL, so
+/// rotated tokens are picked up without a restart.  A TTL of zero re-reads the file on
+/// every send.  Clones share the cached token.
+#[derive(Clone, Debug)]
+pub struct TokenFile {
+    /// The path to the token file
+    path: Redacted<PathBuf>,
+    /// How long a read token is cached
+    ttl: Duration,
+    /// The cached token and when it was read
+    cached: Arc<Mutex<Option<(Instant, Redacted<String>)>>>,
+}
+
+impl TokenFile {
+    /// Create a new `TokenFile` reading from `path`, caching the token for `ttl`
+    pub fn new<P>(path: P, ttl: Duration) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            path: Redacted(path.into()),
+            ttl,
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Get the current token, re-reading the file if the cached token has expired.  An
+    /// empty or whitespace-only token file is an error, rather than sending an empty
+    /// bearer token.
+    pub(crate) fn token(&self) -> crate::error::Result<String> {
+        let mut cached = self.cached.lock().map_err(|e| format!("{}", e))?;
+
+        if let Some((read_at, token)) = &*cached {
+            if read_at.elapsed() < self.ttl {
+                return Ok(token.0.clone());
+            }
+        }
+
+        let token = fs::read_to_string(&self.path.0)
+            .map_err(ErrKind::TokenFile)?
+            .trim()
+            .to_string();
+
+        if token.is_empty() {
+            let empty = io::Error::new(ErrorKind::InvalidData, "the token file is empty");
+            return Err(ErrKind::TokenFile(empty).into());
+        }
+        *cached = Some((Instant::now(), Redacted(token.clone())));
+        Ok(token)
+    }
+}
+
+/// A credential, or where to find one, kept out of `Debug` output so it isn't leaked
+/// into logs
+#[derive(Clone, Default)]
+pub(crate) struct Redacted<T>(pub(crate) T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{Redacted, TokenFile},
+        crate::error::Result,
+        std::{env, fs, time::Duration},
+    };
+
+    #[test]
+    fn rotated_token() -> Result<()> {
+        let path = env::temp_dir().join("libechoexec-rotated-token");
+        fs::write(&path, "first\n")?;
+
+        let token_file = TokenFile::new(&path, Duration::from_secs(0));
+        assert_eq!(token_file.token()?, "first");
+
+        fs::write(&path, "second\n")?;
+        assert_eq!(token_file.token()?, "second");
+
+        fs::write(&path, " \n")?;
+        assert!(token_file.token().is_err());
+
+        fs::remove_file(&path)?;
+        let err = token_file.token().expect_err("missing token file");
+        assert!(!format!("{}", err).contains("rotated-token"));
+        assert!(!format!("{:?}", err).contains("rotated-token"));
+        Ok(())
+    }
+
+    #[test]
+    fn cached_token() -> Result<()> {
+        let path = env::temp_dir().join("libechoexec-cached-token");
+        fs::write(&path, "first")?;
+
+        let token_file = TokenFile::new(&path, Duration::from_secs(60));
+        assert_eq!(token_file.token()?, "first");
+
+        fs::write(&path, "second")?;
+        assert_eq!(token_file.token()?, "first");
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn redacted_debug() {
+        let token_file = TokenFile::new("/run/secrets/echo-token", Duration::from_secs(60));
+        let debug = format!("{:?}", token_file);
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("echo-token"));
+        assert_eq!(format!("{:?}", Redacted("secret")), "<redacted>");
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Retry backoff
+
+use {
+    chrono::{DateTime, Utc},
+    std::{fmt, time::Duration},
+};
+
+/// The source of randomness used to jitter retry backoff delays.
+///
+/// The `Spawner` uses `ThreadRngJitter` by default.  Supply a deterministic
+/// implementation to make backoff delays reproducible in tests.
+pub trait Jitter: fmt::Debug + Send + Sync {
+    /// Pick a delay between zero and `max`
+    fn jitter(&self, max: Duration) -> Duration;
+}
+
+/// Full jitter using the thread local random number generator
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ThreadRngJitter;
+
+impl Jitter for ThreadRngJitter {
+    fn jitter(&self, max: Duration) -> Duration {
+        max.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// The jittered exponential backoff delay, `base * 2^attempt`, before the given retry
+/// attempt (starting at zero)
+pub(crate) fn delay(jitter: &dyn Jitter, base: Duration, attempt: u32) -> Duration {
+    jitter.jitter(base * 2_u32.saturating_pow(attempt))
+}
+
+/// The delay asked for by a `Retry-After` header value, either a number of seconds or
+/// an HTTP-date, relative to `now`.  Dates in the past ask for no delay.  Only RFC 2822
+/// dates, i.e. `Wed, 21 Oct 2015 07:28:00 GMT`, are parsed, the obsolete RFC 850 and
+/// asctime date formats are ignored.
+pub(crate) fn retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{delay, retry_after, Jitter, ThreadRngJitter},
+        chrono::{TimeZone, Utc},
+        std::time::Duration,
+    };
+
+    #[derive(Debug)]
+    struct Half;
+
+    impl Jitter for Half {
+        fn jitter(&self, max: Duration) -> Duration {
+            max / 2
+        }
+    }
+
+    #[test]
+    fn exponential_delay() {
+        let base = Duration::from_millis(100);
+        assert_eq!(delay(&Half, base, 0), Duration::from_millis(50));
+        assert_eq!(delay(&Half, base, 3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        let now = Utc.ymd(2015, 10, 21).and_hms(7, 28, 0);
+        assert_eq!(retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::default())
+        );
+        assert_eq!(retry_after("soon", now), None);
+        assert_eq!(retry_after("-1", now), None);
+    }
+
+    #[test]
+    fn thread_rng_jitter() {
+        let max = Duration::from_millis(100);
+        for _ in 0..100 {
+            assert!(ThreadRngJitter.jitter(max) <= max);
+        }
+    }
+}
@@ -0,0 +1,471 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Batched event submission
+
+use {
+    crate::{
+        echo::{Event, Spawner},
+        error::ErrKind,
+        stream::StreamConfig,
+    },
+    futures::{executor::block_on, FutureExt},
+    slog_try::{try_debug, try_error},
+    std::{
+        panic::AssertUnwindSafe,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::{
+        sync::{
+            mpsc::{self, UnboundedReceiver, UnboundedSender},
+            oneshot,
+        },
+        task::JoinHandle,
+        time::{delay_for, timeout_at, Instant},
+    },
+};
+
+/// The number of times a panicking batching worker is restarted before it's stopped
+const MAX_RESTARTS: u32 = 5;
+
+/// The delay before the first restart of a panicking worker, doubled on each restart
+const RESTART_BACKOFF: Duration = Duration::from_millis(10);
+
+/// How long a worker must run before panicking for its restarts, and the backoff, to be
+/// reset, so unrelated panics spread over the process lifetime don't stop it
+#[cfg(not(test))]
+const STABLE_RUN: Duration = Duration::from_secs(60);
+#[cfg(test)]
+const STABLE_RUN: Duration = Duration::from_millis(500);
+
+/// A command for the batching worker
+#[derive(Debug)]
+enum Command {
+    /// Add an event to the current batch
+    Enqueue(Event),
+    /// Spawn the current batch now, signalling when done
+    Flush(oneshot::Sender<()>),
+    /// Panic, to exercise the supervisor
+    #[cfg(test)]
+    Panic,
+}
+
+/// Accepts individual events and spawns them in batches of up to `max_batch_size`
+/// events.  A partial batch is spawned when `flush_interval` elapses, on `flush`, and
+/// when the `BatchingSpawner` is dropped.  A batch is also spawned early when the next
+/// event would take its approximate serialized size over the `Spawner`
+/// `max_payload_bytes` cap.
+///
+/// The batches are assembled by a background task on the `Spawner` runtime.  The task
+/// is supervised, so a panic while spawning a batch restarts it, after a backoff, rather
+/// than silently discarding every event enqueued afterwards.  The batch being spawned
+/// is dropped, as it may be what panicked.  After 5 restarts, without the task running
+/// for a minute between panics, the task is stopped, and `enqueue` and `flush` fail with
+/// `ErrKind::WorkerDead`.
+///
+/// `flush` and drop block the calling thread, so they must happen in a synchronous
+/// context, never from within an async task.  Dropping the `BatchingSpawner` from a
+/// task on the `Spawner` runtime can deadlock, as it waits for the worker to spawn the
+/// final batch on that runtime.
+#[derive(Debug)]
+pub struct BatchingSpawner {
+    /// The spawner batches are sent with
+    spawner: Arc<Spawner>,
+    /// The worker command channel
+    sender: Option<UnboundedSender<Command>>,
+    /// The supervised worker task
+    worker: Option<JoinHandle<()>>,
+    /// Collapse duplicate events within each batch
+    dedup: Arc<AtomicBool>,
+    /// Set once the worker is stopped after its restart limit
+    dead: Arc<AtomicBool>,
+}
+
+impl BatchingSpawner {
+    /// Create a new `BatchingSpawner` sending batches built from `config` with `spawner`
+    #[must_use]
+    pub fn new(spawner: Spawner, config: StreamConfig) -> Self {
+        let spawner = Arc::new(spawner);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let dedup = Arc::new(AtomicBool::new(false));
+        let dead = Arc::new(AtomicBool::new(false));
+        let worker = spawner.rt().spawn(supervise(
+            spawner.clone(),
+            config,
+            dedup.clone(),
+            dead.clone(),
+            receiver,
+        ));
+
+        Self {
+            spawner,
+            sender: Some(sender),
+            worker: Some(worker),
+            dedup,
+            dead,
+        }
+    }
+
+    /// Collapse duplicate events, as compared by `Event::semantic_eq`, within each batch
+    /// when it's spawned, i.e. for events enqueued repeatedly by retrying callers.  The
+    /// first of each set of duplicates is sent.  Off by default.
+    pub fn set_dedup(&self, dedup: bool) {
+        self.dedup.store(dedup, Ordering::Relaxed);
+    }
+
+    /// The spawner batches are sent with
+    #[must_use]
+    pub fn spawner(&self) -> &Spawner {
+        &self.spawner
+    }
+
+    /// Add an event to the current batch
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::WorkerDead` if the background worker was stopped after repeated
+    /// panics, or `ErrKind::WorkerStopped` if it has otherwise stopped.
+    pub fn enqueue(&self, event: Event) -> crate::error::Result<()> {
+        self.command(Command::Enqueue(event))
+    }
+
+    /// Spawn the current batch now, blocking until it has been spawned
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::WorkerDead` if the background worker was stopped after repeated
+    /// panics, or `ErrKind::WorkerStopped` if it has otherwise stopped.
+    pub fn flush(&self) -> crate::error::Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.command(Command::Flush(tx))?;
+        block_on(rx).map_err(|_| self.stopped())?;
+        Ok(())
+    }
+
+    fn command(&self, command: Command) -> crate::error::Result<()> {
+        if self.dead.load(Ordering::SeqCst) {
+            return Err(ErrKind::WorkerDead.into());
+        }
+        self.sender
+            .as_ref()
+            .ok_or(ErrKind::WorkerStopped)?
+            .send(command)
+            .map_err(|_| self.stopped())?;
+        Ok(())
+    }
+
+    /// The error for a command the worker didn't handle
+    fn stopped(&self) -> ErrKind {
+        if self.dead.load(Ordering::SeqCst) {
+            ErrKind::WorkerDead
+        } else {
+            ErrKind::WorkerStopped
+        }
+    }
+}
+
+impl Drop for BatchingSpawner {
+    fn drop(&mut self) {
+        // Closing the channel makes the worker spawn the final batch and exit.  Waiting
+        // for it ensures the last reference to the spawner, and its runtime, is dropped
+        // here rather than on a runtime thread.
+        self.sender = None;
+
+        if let Some(worker) = self.worker.take() {
+            let _ = block_on(worker);
+        }
+    }
+}
+
+/// Run the batching worker, restarting it with backoff if it panics, up to
+/// `MAX_RESTARTS` times in a row.  A worker that ran for `STABLE_RUN` before panicking
+/// starts over.
+async fn supervise(
+    spawner: Arc<Spawner>,
+    config: StreamConfig,
+    dedup: Arc<AtomicBool>,
+    dead: Arc<AtomicBool>,
+    mut receiver: UnboundedReceiver<Command>,
+) {
+    let logger = config.payload.logger();
+    let mut batch = Vec::with_capacity(config.max_batch_size);
+    let worker = Worker {
+        spawner: &spawner,
+        config: &config,
+        dedup: &dedup,
+    };
+    let mut restarts = 0;
+
+    loop {
+        let started = Instant::now();
+
+        if AssertUnwindSafe(run(&worker, &mut receiver, &mut batch))
+            .catch_unwind()
+            .await
+            .is_ok()
+        {
+            return;
+        }
+
+        if started.elapsed() >= STABLE_RUN {
+            restarts = 0;
+        }
+
+        // The batch may be what panicked, so it isn't spawned again
+        try_error!(
+            logger,
+            "Dropping a batch of {} events after the batching worker panicked",
+            batch.len()
+        );
+        batch.clear();
+
+        if restarts == MAX_RESTARTS {
+            try_error!(
+                logger,
+                "Stopping the batching worker after {} restarts",
+                MAX_RESTARTS
+            );
+            dead.store(true, Ordering::SeqCst);
+            return;
+        }
+        delay_for(RESTART_BACKOFF * 2_u32.pow(restarts)).await;
+        restarts += 1;
+        try_error!(
+            logger,
+            "Restarting the batching worker after a panic, restart {} of {}",
+            restarts,
+            MAX_RESTARTS
+        );
+    }
+}
+
+/// The shared state of the batching worker
+struct Worker<'a> {
+    /// The spawner batches are sent with
+    spawner: &'a Spawner,
+    /// The batching configuration
+    config: &'a StreamConfig,
+    /// Collapse duplicate events within each batch
+    dedup: &'a AtomicBool,
+}
+
+impl Worker<'_> {
+    /// Spawn the batch, logging rather than returning errors so the worker keeps running
+    fn spawn_batch(&self, batch: &mut Vec<Event>) {
+        let logger = self.config.payload.logger();
+
+        if self.dedup.load(Ordering::Relaxed) {
+            let collapsed = collapse_duplicates(batch);
+
+            if collapsed > 0 {
+                try_debug!(
+                    logger,
+                    "Collapsed {} duplicate events in the batch",
+                    collapsed
+                );
+            }
+        }
+
+        if let Err(e) = self.spawner.spawn_batch(self.config, batch) {
+            try_error!(
+                logger,
+                "Dropping a batch of events that failed to spawn: {}",
+                e
+            );
+            batch.clear();
+        }
+    }
+}
+
+/// Assemble and spawn batches until the command channel is closed
+async fn run(
+    worker: &Worker<'_>,
+    receiver: &mut UnboundedReceiver<Command>,
+    batch: &mut Vec<Event>,
+) {
+    let config = worker.config;
+    let limit = worker.spawner.max_payload_bytes();
+    let mut bytes: usize = batch.iter().map(event_size).sum();
+    let mut deadline = Instant::now() + config.flush_interval;
+
+    loop {
+        match timeout_at(deadline, receiver.recv()).await {
+            Ok(Some(Command::Enqueue(event))) => {
+                if batch.is_empty() {
+                    bytes = 0;
+                }
+                let size = event_size(&event);
+
+                if limit.map_or(false, |limit| bytes + size > limit) {
+                    worker.spawn_batch(batch);
+                    deadline = Instant::now() + config.flush_interval;
+                    bytes = 0;
+                }
+                bytes += size;
+                batch.push(event);
+
+                if batch.len() >= config.max_batch_size {
+                    worker.spawn_batch(batch);
+                    deadline = Instant::now() + config.flush_interval;
+                }
+            }
+            Ok(Some(Command::Flush(done))) => {
+                worker.spawn_batch(batch);
+                deadline = Instant::now() + config.flush_interval;
+                let _ = done.send(());
+            }
+            #[cfg(test)]
+            Ok(Some(Command::Panic)) => panic!("injected batching worker panic"),
+            Ok(None) => {
+                worker.spawn_batch(batch);
+                return;
+            }
+            Err(_) => {
+                worker.spawn_batch(batch);
+                deadline = Instant::now() + config.flush_interval;
+            }
+        }
+    }
+}
+
+/// The approximate serialized size of the event in a batch, including its separator
+fn event_size(event: &Event) -> usize {
+    event.serialized_len().map_or(0, |len| len + 1)
+}
+
+/// Collapse the events equal to an earlier event in the batch by `Event::semantic_eq`,
+/// returning the number collapsed
+fn collapse_duplicates(batch: &mut Vec<Event>) -> usize {
+    let before = batch.len();
+    let mut kept: Vec<Event> = Vec::with_capacity(before);
+
+    for event in batch.drain(..) {
+        if !kept.iter().any(|earlier| earlier.semantic_eq(&event)) {
+            kept.push(event);
+        }
+    }
+    *batch = kept;
+    before - batch.len()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{BatchingSpawner, Command, MAX_RESTARTS, STABLE_RUN},
+        crate::{
+            echo::{CollectorUrl, Event, Payload, Spawner},
+            error::{ErrKind, Result},
+            stream::StreamConfig,
+        },
+        std::{thread, time::Duration},
+    };
+
+    /// A payload template for an unreachable collector, so no test traffic is sent
+    fn template() -> Payload {
+        let mut payload = Payload::default();
+        let _ = payload.set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()));
+        payload
+    }
+
+    fn event(i: usize) -> Event {
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .set_routing_key("atlas-dev-promises")
+            .set_message(format!("Message {}", i));
+        echo_event
+    }
+
+    #[test]
+    fn enqueue_and_flush() -> Result<()> {
+        let config = StreamConfig::new(template(), 2, Duration::from_secs(60));
+        let batching = BatchingSpawner::new(Spawner::new()?, config);
+
+        for i in 0..3 {
+            batching.enqueue(event(i))?;
+        }
+        batching.flush()?;
+
+        assert!(batching
+            .spawner()
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 3"#));
+        Ok(())
+    }
+
+    #[test]
+    fn restarts_after_panics() -> Result<()> {
+        let config = StreamConfig::new(template(), 10, Duration::from_secs(60));
+        let batching = BatchingSpawner::new(Spawner::new()?, config);
+
+        // The batch being spawned when the worker panicked is dropped
+        batching.enqueue(event(0))?;
+        batching.command(Command::Panic)?;
+        batching.enqueue(event(1))?;
+        batching.flush()?;
+        assert!(batching
+            .spawner()
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 1"#));
+
+        // Until the restart limit, after which the worker is dead
+        for _ in 0..MAX_RESTARTS {
+            batching.command(Command::Panic)?;
+        }
+        let flushed = batching.flush();
+        assert!(matches!(
+            flushed.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::WorkerDead)
+        ));
+        assert!(matches!(
+            batching
+                .enqueue(event(2))
+                .as_ref()
+                .map_err(crate::error::Err::kind),
+            Err(ErrKind::WorkerDead)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn restarts_reset_after_stable_run() -> Result<()> {
+        let config = StreamConfig::new(template(), 10, Duration::from_secs(60));
+        let batching = BatchingSpawner::new(Spawner::new()?, config);
+
+        // Panics spread out over time don't add up to the restart limit
+        for _ in 0..=MAX_RESTARTS {
+            batching.command(Command::Panic)?;
+            batching.flush()?;
+            thread::sleep(STABLE_RUN + Duration::from_millis(50));
+        }
+        batching.enqueue(event(0))?;
+        batching.flush()?;
+        Ok(())
+    }
+
+    #[test]
+    fn dedup() -> Result<()> {
+        let config = StreamConfig::new(template(), 10, Duration::from_secs(60));
+        let batching = BatchingSpawner::new(Spawner::new()?, config);
+        batching.set_dedup(true);
+
+        for _ in 0..3 {
+            batching.enqueue(event(0))?;
+        }
+        batching.flush()?;
+
+        assert!(batching
+            .spawner()
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 1"#));
+        Ok(())
+    }
+}
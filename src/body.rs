@@ -0,0 +1,149 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Request body preparation
+
+use {
+    flate2::{write::GzEncoder, Compression},
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    std::{
+        fmt::Write as FmtWrite,
+        io::{self, Write},
+    },
+};
+
+/// A request body ready to be sent to the collector
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PreparedBody {
+    /// The body bytes
+    pub(crate) bytes: Vec<u8>,
+    /// The `Content-Encoding` of the bytes, if any
+    pub(crate) encoding: Option<&'static str>,
+}
+
+impl PreparedBody {
+    /// The size of the body
+    pub(crate) fn size(&self, uncompressed: usize) -> BodySize {
+        BodySize {
+            uncompressed,
+            encoded: self.bytes.len(),
+        }
+    }
+}
+
+/// The size of a request body before and after encoding
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct BodySize {
+    /// The size of the encoded events
+    pub(crate) uncompressed: usize,
+    /// The size of the body as sent, after any compression
+    pub(crate) encoded: usize,
+}
+
+/// A writer that counts the bytes written to it, discarding them
+#[derive(Clone, Copy, Debug, Default)]
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The length of the value serialized as JSON, counted without buffering the JSON
+pub(crate) fn json_len<T>(value: &T) -> crate::error::Result<usize>
+where
+    T: Serialize + ?Sized,
+{
+    let mut counter = ByteCounter::default();
+    serde_json::to_writer(&mut counter, value)?;
+    Ok(counter.0)
+}
+
+/// The idempotency key of a request body, the hex encoded SHA-256 hash of the body
+pub(crate) fn idempotency_key(body: &[u8]) -> String {
+    Sha256::digest(body)
+        .iter()
+        .fold(String::with_capacity(64), |mut key, byte| {
+            let _ = write!(key, "{:02x}", byte);
+            key
+        })
+}
+
+/// Prepare the encoded events for sending.  When `compress` is set the content is
+/// gzipped, but the compressed bytes are only used if they are actually smaller than
+/// the original, so tiny payloads are never inflated.
+pub(crate) fn prepare(content: Vec<u8>, compress: bool) -> crate::error::Result<PreparedBody> {
+    if compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content)?;
+        let compressed = encoder.finish()?;
+
+        if compressed.len() < content.len() {
+            return Ok(PreparedBody {
+                bytes: compressed,
+                encoding: Some("gzip"),
+            });
+        }
+    }
+
+    Ok(PreparedBody {
+        bytes: content,
+        encoding: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{idempotency_key, json_len, prepare},
+        crate::error::Result,
+    };
+
+    #[test]
+    fn stable_idempotency_key() {
+        assert_eq!(
+            idempotency_key(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn counted_json_len() -> Result<()> {
+        let values = vec!["a", "bc"];
+        assert_eq!(json_len(&values)?, serde_json::to_vec(&values)?.len());
+        Ok(())
+    }
+
+    #[test]
+    fn tiny_payload_uncompressed() -> Result<()> {
+        let json = r#"[{"routingKey":"","type":"INFO","message":""}]"#.to_string();
+        let body = prepare(json.clone().into_bytes(), true)?;
+        assert_eq!(body.encoding, None);
+        assert_eq!(body.bytes, json.into_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn large_payload_compressed() -> Result<()> {
+        let json = format!(
+            "[{}]",
+            vec![r#"{"routingKey":"","type":"INFO","message":"testing"}"#; 100].join(",")
+        );
+        let body = prepare(json.clone().into_bytes(), true)?;
+        assert_eq!(body.encoding, Some("gzip"));
+        assert!(body.bytes.len() < json.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `Event` builder
+
+use {
+    crate::echo::{Event, EventType, Response},
+    std::collections::HashMap,
+    uuid::Uuid,
+};
+
+/// A builder for an `Event`.  The routing key, event type, and message the collector
+/// requires are taken up front, so they can't be forgotten.  Everything else is optional.
+///
+/// ```
+/// # use libechoexec::{EventBuilder, EventType};
+/// let event = EventBuilder::new("atlas-dev-promises", EventType::Info, "Fetched promises")
+///     .host("web-01")
+///     .build();
+/// assert_eq!(event.routing_key(), "atlas-dev-promises");
+/// ```
+#[derive(Clone, Debug)]
+pub struct EventBuilder {
+    /// The event being built
+    event: Event,
+}
+
+impl EventBuilder {
+    /// Create a new `EventBuilder` with the required event fields
+    pub fn new<R, M>(routing_key: R, event_type: EventType, message: M) -> Self
+    where
+        R: Into<String>,
+        M: Into<String>,
+    {
+        let mut event = Event::default();
+        let _ = event
+            .set_routing_key(routing_key)
+            .set_event_type(event_type)
+            .set_message(message);
+        Self { event }
+    }
+
+    /// Set the correlation id
+    #[must_use]
+    pub fn correlation_id(mut self, correlation_id: Uuid) -> Self {
+        let _ = self.event.set_correlation_id(Some(correlation_id));
+        self
+    }
+
+    /// Set the timestamp, in milliseconds since the epoch
+    #[must_use]
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        let _ = self.event.set_timestamp(Some(timestamp));
+        self
+    }
+
+    /// Set the message detail key/value pairs
+    #[must_use]
+    pub fn message_detail(mut self, message_detail: HashMap<String, String>) -> Self {
+        let _ = self.event.set_message_detail(Some(message_detail));
+        self
+    }
+
+    /// Set the host the event originated on
+    #[must_use]
+    pub fn host<T>(mut self, host: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self.event.set_host(Some(host));
+        self
+    }
+
+    /// Set the application version
+    #[must_use]
+    pub fn application_version<T>(mut self, application_version: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self
+            .event
+            .set_application_version(Some(application_version));
+        self
+    }
+
+    /// Set the data center
+    #[must_use]
+    pub fn data_center<T>(mut self, data_center: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self.event.set_data_center(Some(data_center));
+        self
+    }
+
+    /// Set the tenant id
+    #[must_use]
+    pub fn tenant_id<T>(mut self, tenant_id: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self.event.set_tenant_id(Some(tenant_id));
+        self
+    }
+
+    /// Set the client host name
+    #[must_use]
+    pub fn client_host_name<T>(mut self, client_host_name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self.event.set_client_host_name(Some(client_host_name));
+        self
+    }
+
+    /// Set the destination host name
+    #[must_use]
+    pub fn destination_host_name<T>(mut self, destination_host_name: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self
+            .event
+            .set_destination_host_name(Some(destination_host_name));
+        self
+    }
+
+    /// Set the destination path
+    #[must_use]
+    pub fn destination_path<T>(mut self, destination_path: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let _ = self.event.set_destination_path(Some(destination_path));
+        self
+    }
+
+    /// Set the start timestamp, in milliseconds since the epoch
+    #[must_use]
+    pub fn start_timestamp(mut self, start_timestamp: u64) -> Self {
+        let _ = self.event.set_start_timestamp(Some(start_timestamp));
+        self
+    }
+
+    /// Set the finish timestamp, in milliseconds since the epoch
+    #[must_use]
+    pub fn finish_timestamp(mut self, finish_timestamp: u64) -> Self {
+        let _ = self.event.set_finish_timestamp(Some(finish_timestamp));
+        self
+    }
+
+    /// Set the duration, in milliseconds
+    #[must_use]
+    pub fn duration_in_ms(mut self, duration_in_ms: u64) -> Self {
+        let _ = self.event.set_duration_in_ms(Some(duration_in_ms));
+        self
+    }
+
+    /// Set the HTTP response code of a performance event
+    #[must_use]
+    pub fn response_code(mut self, response_code: u16) -> Self {
+        let _ = self.event.set_response_code(Some(response_code));
+        self
+    }
+
+    /// Set the generic response
+    #[must_use]
+    pub fn response(mut self, response: Response) -> Self {
+        let _ = self.event.set_response(Some(response));
+        self
+    }
+
+    /// Build the `Event`
+    #[must_use]
+    pub fn build(self) -> Event {
+        self.event
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::EventBuilder,
+        crate::echo::{EventType, Response},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn build() {
+        let mut message_detail = HashMap::new();
+        let _ = message_detail.insert("key".to_string(), "value".to_string());
+
+        let event = EventBuilder::new("atlas-dev-promises", EventType::Performance, "Done")
+            .message_detail(message_detail.clone())
+            .response_code(200)
+            .response(Response::Success)
+            .build();
+
+        assert_eq!(event.routing_key(), "atlas-dev-promises");
+        assert_eq!(*event.event_type(), EventType::Performance);
+        assert_eq!(event.message(), "Done");
+        assert_eq!(*event.message_detail(), Some(message_detail));
+        assert_eq!(*event.response_code(), Some(200));
+        assert_eq!(*event.response(), Some(Response::Success));
+        assert!(event.host().is_none());
+    }
+}
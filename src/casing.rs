@@ -0,0 +1,167 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Collector field casing
+
+use {
+    crate::echo::Event,
+    serde::Serialize,
+    serde_json::{Map, Value},
+};
+
+/// The casing convention applied to `Event` field names at serialization
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldCasing {
+    /// camelCase field names, i.e. `routingKey` (the Echo collector default)
+    Camel,
+    /// snake_case field names, i.e. `routing_key`
+    Snake,
+}
+
+impl Default for FieldCasing {
+    fn default() -> Self {
+        Self::Camel
+    }
+}
+
+impl FieldCasing {
+    /// Serialize the events to a JSON array, using this casing for the field names.
+    /// The keys inside `message_detail` are left untouched.
+    ///
+    /// # Errors
+    ///
+    pub fn serialize(self, events: &[Event]) -> crate::error::Result<String> {
+        match self {
+            Self::Camel => Ok(serde_json::to_string(events)?),
+            Self::Snake => {
+                let events = match serde_json::to_value(events)? {
+                    Value::Array(events) => events.into_iter().map(snake_case_keys).collect(),
+                    other => vec![other],
+                };
+                Ok(serde_json::to_string(&events)?)
+            }
+        }
+    }
+
+    /// Serialize a single event to a bare JSON object, using this casing for the field
+    /// names.  The keys inside `message_detail` are left untouched.
+    ///
+    /// # Errors
+    ///
+    pub fn serialize_event(self, event: &Event) -> crate::error::Result<String> {
+        self.serialize_object(event)
+    }
+
+    /// Serialize each item to a bare JSON object, using this casing for the field
+    /// names.  Items that can't be serialized are skipped rather than failing the batch,
+    /// and returned with their index and serialization error.
+    pub(crate) fn serialize_each<T>(
+        self,
+        items: &[T],
+    ) -> (Vec<String>, Vec<(usize, crate::error::Err)>)
+    where
+        T: Serialize,
+    {
+        let mut serialized = Vec::with_capacity(items.len());
+        let mut errors = vec![];
+
+        for (idx, item) in items.iter().enumerate() {
+            match self.serialize_object(item) {
+                Ok(json) => serialized.push(json),
+                Err(e) => errors.push((idx, e)),
+            }
+        }
+        (serialized, errors)
+    }
+
+    fn serialize_object<T>(self, value: &T) -> crate::error::Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Self::Camel => Ok(serde_json::to_string(value)?),
+            Self::Snake => Ok(serde_json::to_string(&snake_case_keys(
+                serde_json::to_value(value)?,
+            ))?),
+        }
+    }
+}
+
+fn snake_case_keys(event: Value) -> Value {
+    match event {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (to_snake_case(&key), value))
+                .collect::<Map<String, Value>>(),
+        ),
+        other => other,
+    }
+}
+
+fn to_snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+
+    for ch in key.chars() {
+        if ch.is_ascii_uppercase() {
+            snake.push('_');
+            snake.push(ch.to_ascii_lowercase());
+        } else {
+            snake.push(ch);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{to_snake_case, FieldCasing},
+        crate::{echo::Event, error::Result},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn snake_case() {
+        assert_eq!(to_snake_case("routingKey"), "routing_key");
+        assert_eq!(to_snake_case("durationInMs"), "duration_in_ms");
+        assert_eq!(to_snake_case("type"), "type");
+    }
+
+    #[test]
+    fn serialize_snake() -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_routing_key("atlas-dev-promises");
+        let mut message_detail = HashMap::new();
+        let _ = message_detail.insert("someKey".to_string(), "b".to_string());
+        let _ = echo_event.set_message_detail(Some(message_detail));
+
+        assert_eq!(
+            FieldCasing::Camel.serialize(&[echo_event.clone()])?,
+            r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","messageDetail":{"someKey":"b"}}]"#
+        );
+        assert_eq!(
+            FieldCasing::Snake.serialize(&[echo_event])?,
+            r#"[{"message":"","message_detail":{"someKey":"b"},"routing_key":"atlas-dev-promises","type":"INFO"}]"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_each_skips_failures() {
+        // JSON object keys must be strings
+        let items = vec![
+            vec![(vec![1], "a")].into_iter().collect::<HashMap<_, _>>(),
+            HashMap::new(),
+        ];
+        let (serialized, errors) = FieldCasing::Camel.serialize_each(&items);
+        assert_eq!(serialized, vec!["{}"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, 0);
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Collector circuit breaking
+
+use std::{
+    convert::TryFrom,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The state of the `Spawner` circuit breaker
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CircuitState {
+    /// Sends go to the collector as usual
+    Closed,
+    /// The collector is failing, sends fail immediately with `ErrKind::CircuitOpen`
+    Open,
+    /// The cooldown has passed, the next send probes the collector for recovery
+    HalfOpen,
+}
+
+/// Opens after `threshold` consecutive failures within a window, failing sends
+/// immediately until a cooldown passes.  After the cooldown, a single probe send is let
+/// through, closing the circuit if it succeeds and reopening it if it fails.
+///
+/// Times are kept as milliseconds since the breaker was created, so the state can be
+/// shared between sends without locking.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    /// The consecutive failures that open the circuit
+    threshold: u32,
+    /// The window the consecutive failures must occur within, in milliseconds
+    window: u64,
+    /// How long the circuit stays open before probing, in milliseconds
+    cooldown: u64,
+    /// The time the breaker was created
+    epoch: Instant,
+    /// The consecutive failures in the current window
+    failures: AtomicU32,
+    /// When the current failure window started
+    window_start: AtomicU64,
+    /// When the circuit opened, plus one, or zero while closed
+    opened_at: AtomicU64,
+}
+
+impl CircuitBreaker {
+    /// Create a new, closed, circuit breaker
+    pub(crate) fn new(threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            window: millis(window),
+            cooldown: millis(cooldown),
+            epoch: Instant::now(),
+            failures: AtomicU32::new(0),
+            window_start: AtomicU64::new(0),
+            opened_at: AtomicU64::new(0),
+        }
+    }
+
+    /// The milliseconds since the breaker was created
+    fn now(&self) -> u64 {
+        millis(self.epoch.elapsed())
+    }
+
+    /// The current state of the circuit
+    pub(crate) fn state(&self) -> CircuitState {
+        match self.opened_at.load(Ordering::SeqCst) {
+            0 => CircuitState::Closed,
+            opened if self.now() < (opened - 1).saturating_add(self.cooldown) => CircuitState::Open,
+            _ => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a send may go to the collector.  Once the cooldown has passed, a single
+    /// probe is let through, and the cooldown restarts, so a probe that never completes
+    /// doesn't hold the circuit half open.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let opened = self.opened_at.load(Ordering::SeqCst);
+
+        if opened == 0 {
+            return true;
+        }
+
+        let now = self.now();
+
+        if now < (opened - 1).saturating_add(self.cooldown) {
+            return false;
+        }
+
+        self.opened_at
+            .compare_exchange(opened, now + 1, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Record a send the collector handled, closing the circuit
+    pub(crate) fn record_success(&self) {
+        self.failures.store(0, Ordering::SeqCst);
+        self.opened_at.store(0, Ordering::SeqCst);
+    }
+
+    /// Record a failed send, opening the circuit on reaching the threshold.  A failure
+    /// while the circuit is open, i.e. a failed probe, restarts the cooldown.
+    pub(crate) fn record_failure(&self) {
+        let now = self.now();
+
+        if self.opened_at.load(Ordering::SeqCst) != 0 {
+            self.opened_at.store(now + 1, Ordering::SeqCst);
+            return;
+        }
+
+        let elapsed = now.saturating_sub(self.window_start.load(Ordering::SeqCst));
+        let failures = if self.failures.load(Ordering::SeqCst) == 0 || elapsed > self.window {
+            self.window_start.store(now, Ordering::SeqCst);
+            self.failures.store(1, Ordering::SeqCst);
+            1
+        } else {
+            self.failures.fetch_add(1, Ordering::SeqCst) + 1
+        };
+
+        if failures >= self.threshold {
+            self.opened_at.store(now + 1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The duration in whole milliseconds
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::max_value())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{CircuitBreaker, CircuitState},
+        std::{thread, time::Duration},
+    };
+
+    #[test]
+    fn opens_and_recovers() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60), Duration::from_millis(50));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+
+        // A single probe after the cooldown, a failed probe reopens the circuit
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.try_acquire());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn failures_outside_the_window() {
+        let breaker = CircuitBreaker::new(2, Duration::from_millis(10), Duration::from_secs(60));
+        breaker.record_failure();
+        thread::sleep(Duration::from_millis(30));
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // Successes break the run of failures
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
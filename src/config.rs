@@ -0,0 +1,359 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `Spawner` HTTP client configuration
+
+use {
+    crate::{
+        auth::Redacted, circuit::CircuitBreaker, echo::CollectorUrl, limit::RateLimitPolicy,
+        metadata::DataCenterResolver,
+    },
+    getset::Setters,
+    hyper::Uri,
+    hyper_proxy::{Intercept, Proxy},
+    slog::Logger,
+    std::{env, sync::Arc, time::Duration},
+};
+
+/// The settings of a `Spawner`, see `Spawner::with_config`.
+///
+/// The defaults suit most applications.  Tune them down for small sidecars, or up for
+/// high-throughput aggregators.  The single-option constructors, i.e.
+/// `Spawner::for_tenant`, are shorthand for `Spawner::with_config` with one of these
+/// set, so set them here to combine several.
+#[derive(Clone, Debug, Setters)]
+pub struct SpawnerConfig {
+    /// The maximum number of concurrent DNS lookups.  Lookups run on the runtime
+    /// blocking pool, so this is the number of threads resolving at once.
+    #[set = "pub"]
+    dns_threads: usize,
+    /// The maximum number of idle pooled connections kept per collector host
+    #[set = "pub"]
+    pool_max_idle_per_host: usize,
+    /// How long an idle pooled connection is kept, `None` keeps them indefinitely
+    #[set = "pub"]
+    pool_idle_timeout: Option<Duration>,
+    /// The HTTP proxy all collector requests are sent through.  If unset, the
+    /// `HTTPS_PROXY` environment variable is used, skipping any hosts listed in
+    /// `NO_PROXY`.
+    #[set = "pub"]
+    proxy: Option<Uri>,
+    /// Limit sends to this many requests per second, protecting the shared collector
+    /// from bursts.  Bursts of up to a second's worth of requests are allowed.  `None`
+    /// (the default) is unlimited.
+    #[set = "pub"]
+    max_requests_per_sec: Option<u32>,
+    /// What to do with sends over the `max_requests_per_sec` rate
+    #[set = "pub"]
+    rate_limit_policy: RateLimitPolicy,
+    /// Speak HTTP/2 to every collector from the first byte, without negotiating it (the
+    /// `hyper` `http2_only` client option), over both cleartext and TLS connections.
+    ///
+    /// Multiplexing many small event POSTs over one connection cuts the latency and
+    /// connection churn of HTTP/1.1, but every request then shares one TCP connection
+    /// per host, so a single lost packet stalls them all, and a collector or proxy that
+    /// doesn't speak HTTP/2 fails every request.  With it, both TLS backends offer only
+    /// `h2` via ALPN.  Without it, the `rustls` backend still negotiates HTTP/2 via ALPN
+    /// with collectors that offer it, but the `tls` backend stays on HTTP/1.1, as
+    /// `hyper-tls` can't hand a negotiated HTTP/2 connection to `hyper`.  Off by default.
+    #[set = "pub"]
+    http2_prior_knowledge: bool,
+    /// Never fall back to HTTP/1.1 over TLS: only `h2` is offered via ALPN, so a
+    /// collector that doesn't speak HTTP/2 fails the handshake.  With the `rustls`
+    /// backend, HTTP/2 is negotiated via ALPN, and cleartext collectors are still spoken
+    /// to over HTTP/1.1 unless `http2_prior_knowledge` is set.  The `tls` backend can't
+    /// negotiate HTTP/2, so there this implies `http2_prior_knowledge`.  Off by default.
+    #[set = "pub"]
+    http2_only: bool,
+    /// Accept collector certificates that don't verify, i.e. the self-signed
+    /// certificates of stage collectors.  This leaves sends open to man-in-the-middle
+    /// attacks, so turn it off to verify collector certificates in production.  Only the
+    /// `tls` backend honors it, the `rustls` backend always verifies certificates.  On by
+    /// default, as the `tls` backend has always accepted invalid certificates.
+    #[set = "pub"]
+    accept_invalid_certs: bool,
+    /// Open the circuit breaker after this many consecutive failed sends (connection
+    /// errors, timeouts, and 5xx responses) within `circuit_window`.  While the circuit
+    /// is open, sends fail immediately with `ErrKind::CircuitOpen` rather than waiting
+    /// on a collector that is down.  `None` (the default) disables the breaker.
+    #[set = "pub"]
+    circuit_threshold: Option<u32>,
+    /// The window the consecutive failures must occur within
+    #[set = "pub"]
+    circuit_window: Duration,
+    /// How long the circuit stays open before a single send probes the collector
+    #[set = "pub"]
+    circuit_cooldown: Duration,
+    /// The application sending events, i.e. `myapp/1.0`, sent as the `User-Agent` ahead
+    /// of the crate user agent (`myapp/1.0 libechoexec/0.1.0`) for collector-side
+    /// attribution
+    #[set = "pub"]
+    user_agent: Option<String>,
+    /// The client certificate presented to collectors requiring mutual TLS, see
+    /// `set_identity` and `set_pem_identity`
+    identity: Option<ClientIdentity>,
+    /// The tenant id stamped on every event sent, replacing any tenant id already set
+    /// on the event, so events can't be sent without it or on behalf of another tenant
+    #[set = "pub"]
+    tenant_id: Option<String>,
+    /// The token sent as `Authorization: Bearer <token>` on every request, see
+    /// `set_bearer_token`
+    bearer_token: Option<Redacted<String>>,
+    /// The logger used whenever a payload has no logger of its own, see
+    /// `Spawner::set_logger`
+    #[set = "pub"]
+    logger: Option<Logger>,
+    /// The collector every payload without a url of its own is sent to, see
+    /// `Spawner::set_default_url`
+    #[set = "pub"]
+    default_url: Option<CollectorUrl>,
+    /// Resolves the default data center from a cloud metadata service as the spawner is
+    /// built, see `set_data_center_resolver`
+    data_center_resolver: Option<Arc<dyn DataCenterResolver>>,
+    /// The default data center, set on any event without one.  With a
+    /// `data_center_resolver`, it's the fallback used if the metadata service can't be
+    /// reached or doesn't respond quickly.
+    #[set = "pub"]
+    data_center: Option<String>,
+}
+
+/// A client certificate for mutual TLS
+#[derive(Clone, Debug)]
+pub(crate) enum ClientIdentity {
+    /// A DER encoded PKCS #12 archive and its password
+    #[cfg(feature = "tls")]
+    Pkcs12(Vec<u8>, Redacted<String>),
+    /// A PEM encoded certificate chain and PKCS #8 private key
+    Pem(Vec<u8>, Redacted<Vec<u8>>),
+}
+
+impl Default for SpawnerConfig {
+    fn default() -> Self {
+        Self {
+            dns_threads: 4,
+            pool_max_idle_per_host: usize::MAX,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            proxy: None,
+            max_requests_per_sec: None,
+            rate_limit_policy: RateLimitPolicy::default(),
+            http2_prior_knowledge: false,
+            http2_only: false,
+            accept_invalid_certs: true,
+            circuit_threshold: None,
+            circuit_window: Duration::from_secs(60),
+            circuit_cooldown: Duration::from_secs(30),
+            user_agent: None,
+            identity: None,
+            tenant_id: None,
+            bearer_token: None,
+            logger: None,
+            default_url: None,
+            data_center_resolver: None,
+            data_center: None,
+        }
+    }
+}
+
+impl SpawnerConfig {
+    /// Present the client certificate in the DER encoded PKCS #12 archive, decrypted
+    /// with `password`, to collectors requiring mutual TLS.  Some environments require
+    /// this to send to the `Prod` `CollectorUrl`.  An archive that can't be decrypted
+    /// fails `Spawner::with_config` with `ErrKind::NativeTLS`.
+    #[cfg(feature = "tls")]
+    pub fn set_identity(&mut self, pkcs12_der: &[u8], password: &str) -> &mut Self {
+        self.identity = Some(ClientIdentity::Pkcs12(
+            pkcs12_der.to_vec(),
+            Redacted(password.to_string()),
+        ));
+        self
+    }
+
+    /// Present the PEM encoded client certificate chain and PKCS #8 private key to
+    /// collectors requiring mutual TLS.  Unlike `set_identity`, this works with both the
+    /// `tls` and `rustls` backends.  A chain or key that can't be parsed fails
+    /// `Spawner::with_config`.
+    pub fn set_pem_identity(&mut self, cert_chain_pem: &[u8], key_pem: &[u8]) -> &mut Self {
+        self.identity = Some(ClientIdentity::Pem(
+            cert_chain_pem.to_vec(),
+            Redacted(key_pem.to_vec()),
+        ));
+        self
+    }
+
+    /// Authenticate with the collector by sending `Authorization: Bearer <token>` on
+    /// every request.  Use `Spawner::set_token` to rotate the token.
+    pub fn set_bearer_token<T>(&mut self, token: T) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.bearer_token = Some(Redacted(token.into()));
+        self
+    }
+
+    /// Resolve the default data center from a cloud metadata service as the spawner is
+    /// built, falling back to `data_center`
+    pub fn set_data_center_resolver<R>(&mut self, resolver: R) -> &mut Self
+    where
+        R: DataCenterResolver + 'static,
+    {
+        self.data_center_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// The client certificate for mutual TLS, if any
+    pub(crate) fn identity(&self) -> Option<&ClientIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// The tenant id stamped on every event, if any
+    pub(crate) fn tenant_id(&self) -> Option<&String> {
+        self.tenant_id.as_ref()
+    }
+
+    /// The initial bearer token, if any
+    pub(crate) fn bearer_token(&self) -> Option<&Redacted<String>> {
+        self.bearer_token.as_ref()
+    }
+
+    /// The initial spawner logger, if any
+    pub(crate) fn logger(&self) -> Option<&Logger> {
+        self.logger.as_ref()
+    }
+
+    /// The initial default collector, if any
+    pub(crate) fn default_url(&self) -> Option<&CollectorUrl> {
+        self.default_url.as_ref()
+    }
+
+    /// The data center resolver, if any
+    pub(crate) fn data_center_resolver(&self) -> Option<&dyn DataCenterResolver> {
+        self.data_center_resolver.as_deref()
+    }
+
+    /// The default data center, or the resolver fallback
+    pub(crate) fn data_center(&self) -> Option<&String> {
+        self.data_center.as_ref()
+    }
+
+    /// The maximum number of concurrent DNS lookups
+    pub(crate) fn dns_threads(&self) -> usize {
+        self.dns_threads.max(1)
+    }
+
+    /// The maximum number of idle pooled connections per host
+    pub(crate) fn pool_max_idle_per_host(&self) -> usize {
+        self.pool_max_idle_per_host
+    }
+
+    /// How long an idle pooled connection is kept
+    pub(crate) fn pool_idle_timeout(&self) -> Option<Duration> {
+        self.pool_idle_timeout
+    }
+
+    /// Whether every connection speaks HTTP/2 without negotiation
+    pub(crate) fn http2_prior_knowledge(&self) -> bool {
+        self.http2_prior_knowledge
+    }
+
+    /// Whether only `h2` is offered via ALPN
+    pub(crate) fn http2_only(&self) -> bool {
+        self.http2_only
+    }
+
+    /// Whether collector certificates that don't verify are accepted
+    pub(crate) fn accept_invalid_certs(&self) -> bool {
+        self.accept_invalid_certs
+    }
+
+    /// The maximum number of requests per second
+    pub(crate) fn max_requests_per_sec(&self) -> Option<u32> {
+        self.max_requests_per_sec
+    }
+
+    /// What to do with sends over the rate
+    pub(crate) fn rate_limit_policy(&self) -> RateLimitPolicy {
+        self.rate_limit_policy
+    }
+
+    /// The application user agent, if any
+    pub(crate) fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// The circuit breaker, if enabled
+    pub(crate) fn circuit_breaker(&self) -> Option<CircuitBreaker> {
+        self.circuit_threshold.map(|threshold| {
+            CircuitBreaker::new(threshold, self.circuit_window, self.circuit_cooldown)
+        })
+    }
+
+    /// The configured proxy, or the proxy from the environment, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::HyperHTTP` if `HTTPS_PROXY` isn't a valid uri.
+    pub(crate) fn resolve_proxy(&self) -> crate::error::Result<Option<Proxy>> {
+        if let Some(uri) = &self.proxy {
+            return Ok(Some(Proxy::new(Intercept::All, uri.clone())));
+        }
+
+        let uri = match env_var(&["HTTPS_PROXY", "https_proxy"]) {
+            Some(uri) => uri.parse::<Uri>().map_err(hyper::http::Error::from)?,
+            None => return Ok(None),
+        };
+        let no_proxy: Vec<String> = env_var(&["NO_PROXY", "no_proxy"])
+            .map(|hosts| {
+                hosts
+                    .split(',')
+                    .map(|host| host.trim().trim_start_matches('.').to_lowercase())
+                    .filter(|host| !host.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if no_proxy.iter().any(|host| host == "*") {
+            return Ok(None);
+        }
+
+        let intercept = move |_: Option<&str>, host: Option<&str>, _: Option<u16>| {
+            host.map_or(true, |host| !is_no_proxy(&no_proxy, host))
+        };
+        Ok(Some(Proxy::new(Intercept::Custom(intercept.into()), uri)))
+    }
+}
+
+/// The first of the environment variables that is set and non-empty
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Whether the host matches, or is a subdomain of, one of the `NO_PROXY` hosts
+fn is_no_proxy(no_proxy: &[String], host: &str) -> bool {
+    let host = host.to_lowercase();
+    no_proxy
+        .iter()
+        .any(|np| host == *np || host.ends_with(&format!(".{}", np)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_no_proxy;
+
+    #[test]
+    fn no_proxy() {
+        let no_proxy = vec!["localhost".to_string(), "corp.example.com".to_string()];
+        assert!(is_no_proxy(&no_proxy, "localhost"));
+        assert!(is_no_proxy(&no_proxy, "echo.corp.example.com"));
+        assert!(is_no_proxy(&no_proxy, "Echo.Corp.Example.com"));
+        assert!(!is_no_proxy(&no_proxy, "example.com"));
+        assert!(!is_no_proxy(&no_proxy, "notcorp.example.com"));
+    }
+}
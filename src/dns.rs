@@ -0,0 +1,63 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Concurrency limited DNS resolution
+
+use {
+    hyper::{
+        client::connect::dns::{GaiAddrs, GaiResolver, Name},
+        service::Service,
+    },
+    std::{
+        future::Future,
+        io,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll},
+    },
+    tokio::sync::Semaphore,
+};
+
+/// A `getaddrinfo` resolver that caps the number of lookups running at once
+#[derive(Clone, Debug)]
+pub(crate) struct LimitedResolver {
+    /// The resolver doing the lookups
+    inner: GaiResolver,
+    /// One permit per concurrent lookup
+    permits: Arc<Semaphore>,
+}
+
+impl LimitedResolver {
+    /// Create a new resolver running at most `limit` lookups at once
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            inner: GaiResolver::new(),
+            permits: Arc::new(Semaphore::new(limit)),
+        }
+    }
+}
+
+impl Service<Name> for LimitedResolver {
+    type Response = GaiAddrs;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<GaiAddrs, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let permits = self.permits.clone();
+
+        Box::pin(async move {
+            let _permit = permits.acquire().await;
+            inner.call(name).await
+        })
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! In-flight send tracking for draining on shutdown
+
+use {
+    slog::{warn, Logger},
+    slog_try::try_warn,
+    std::{
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, RwLock,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// How often `InFlight::wait` checks for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Counts the spawned sends that haven't completed, and whether new sends are accepted
+#[derive(Debug, Default)]
+pub(crate) struct InFlight {
+    /// The number of spawned sends that haven't completed
+    count: AtomicUsize,
+    /// Set once new sends are no longer accepted
+    closed: AtomicBool,
+}
+
+/// Marks a spawned send as in-flight until dropped
+#[derive(Debug)]
+pub(crate) struct InFlightGuard {
+    /// The tracker to decrement on drop
+    in_flight: Arc<InFlight>,
+}
+
+/// Warns about the sends still in flight when dropped, i.e. when the last clone of a
+/// `Spawner` is dropped without a `shutdown`, abandoning them
+#[derive(Debug)]
+pub(crate) struct AbandonWarning {
+    /// The in-flight sends
+    in_flight: Arc<InFlight>,
+    /// The logger warned through
+    logger: Arc<RwLock<Option<Logger>>>,
+}
+
+impl AbandonWarning {
+    /// Create a new warning for the given in-flight sends, through the given logger
+    pub(crate) fn new(in_flight: Arc<InFlight>, logger: Arc<RwLock<Option<Logger>>>) -> Self {
+        Self { in_flight, logger }
+    }
+}
+
+impl Drop for AbandonWarning {
+    fn drop(&mut self) {
+        let count = self.in_flight.count.load(Ordering::SeqCst);
+
+        if count > 0 {
+            let logger = self.logger.read().ok().and_then(|logger| logger.clone());
+            try_warn!(
+                logger,
+                "Dropping the Echo spawner with {} sends in flight, they are abandoned",
+                count
+            );
+        }
+    }
+}
+
+impl InFlight {
+    /// Track a new send, or `None` if new sends are no longer accepted
+    pub(crate) fn enter(self: &Arc<Self>) -> Option<InFlightGuard> {
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+        let _ = self.count.fetch_add(1, Ordering::SeqCst);
+        Some(InFlightGuard {
+            in_flight: self.clone(),
+        })
+    }
+
+    /// Stop accepting new sends
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether new sends are no longer accepted, i.e. once shutdown has started
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Block until every tracked send has completed or `deadline` passes.  Returns
+    /// whether every send completed.
+    pub(crate) fn wait(&self, deadline: Instant) -> bool {
+        while self.count.load(Ordering::SeqCst) > 0 {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        true
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let _ = self.in_flight.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{AbandonWarning, InFlight},
+        slog::{o, Drain, Logger, Never, OwnedKVList, Record},
+        std::{
+            sync::{Arc, Mutex, RwLock},
+            time::{Duration, Instant},
+        },
+    };
+
+    /// Captures the logged messages
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Drain for Capture {
+        type Ok = ();
+        type Err = Never;
+
+        fn log(&self, record: &Record<'_>, _: &OwnedKVList) -> Result<(), Never> {
+            if let Ok(mut messages) = self.0.lock() {
+                messages.push(record.msg().to_string());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn abandon_warning() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let logger = Logger::root(Capture(messages.clone()).fuse(), o!());
+        let logger = Arc::new(RwLock::new(Some(logger)));
+        let in_flight = Arc::new(InFlight::default());
+
+        drop(AbandonWarning::new(in_flight.clone(), logger.clone()));
+        assert!(messages.lock().map(|m| m.is_empty()).unwrap_or(false));
+
+        let _guard = in_flight.enter();
+        drop(AbandonWarning::new(in_flight, logger));
+        assert_eq!(
+            messages.lock().map(|m| m.clone()).unwrap_or_default(),
+            vec!["Dropping the Echo spawner with 1 sends in flight, they are abandoned"]
+        );
+    }
+
+    #[test]
+    fn close_and_wait() {
+        let in_flight = Arc::new(InFlight::default());
+        let guard = in_flight.enter();
+        assert!(guard.is_some());
+        assert!(!in_flight.wait(Instant::now() + Duration::from_millis(20)));
+
+        in_flight.close();
+        assert!(in_flight.enter().is_none());
+
+        drop(guard);
+        assert!(in_flight.wait(Instant::now()));
+    }
+}
@@ -9,31 +9,268 @@
 //! Echo Structs
 
 use {
-    crate::error::ErrKind,
-    getset::Setters,
-    hyper::{body::HttpBody, client::HttpConnector, Body, Client, Request},
-    hyper_tls::HttpsConnector,
+    crate::{
+        ack::{BatchAck, BatchRejection, CollectorAck},
+        auth::{Redacted, TokenFile},
+        backoff::{self, Jitter, ThreadRngJitter},
+        body::{self, BodySize, PreparedBody},
+        casing::FieldCasing,
+        circuit::{CircuitBreaker, CircuitState},
+        config::SpawnerConfig,
+        drain::{AbandonWarning, InFlight, InFlightGuard},
+        error::ErrKind,
+        format::PayloadFormat,
+        limit::{ByteBudget, HostLimits, RateLimitPolicy, RateLimiter, SendLimits},
+        logging::{LogLevels, Outcome},
+        metadata::{self, DataCenterResolver},
+        metrics::{Metrics, NoopMetrics},
+        openmetrics::EventCounts,
+        pause::{PauseGate, PausePolicy, DEFAULT_PAUSE_BUFFER_CAPACITY},
+        sampling::SamplingPolicy,
+        stats::{BytesSent, ShutdownSummary, Stats},
+        timestamp::Timestamp,
+        tls::{self, HttpsClient},
+        validate::{InvalidEventPolicy, Strictness},
+        wrapper::RootWrapper,
+    },
+    chrono::{DateTime, Utc},
+    futures::{
+        channel::mpsc,
+        executor::block_on,
+        future::{self, Future},
+        stream, Sink, SinkExt, StreamExt,
+    },
+    getset::{Getters, Setters},
+    hyper::{
+        body::{Bytes, HttpBody},
+        header::{
+            HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_LENGTH,
+            CONTENT_TYPE, RETRY_AFTER,
+        },
+        Body, Request, StatusCode,
+    },
     lazy_static::lazy_static,
-    native_tls::TlsConnector,
     serde::{
         de::{self, Deserialize as Deser, Deserializer, Visitor},
         ser::{Serialize as Ser, Serializer},
     },
     serde_derive::{Deserialize, Serialize},
-    slog::{error, trace, Logger},
-    slog_try::{try_error, try_trace},
-    std::{collections::HashMap, error::Error, fmt, io::Write},
-    tokio::runtime::Runtime,
+    slog::{debug, info, warn, Logger},
+    slog_try::{try_debug, try_info, try_trace, try_warn},
+    std::{
+        borrow::Cow,
+        collections::{hash_map::DefaultHasher, HashMap},
+        convert::TryFrom,
+        env::{self, VarError},
+        error::Error,
+        fmt,
+        hash::{Hash, Hasher},
+        io::Write,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, RwLock,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::{
+        runtime::{Handle, Runtime},
+        sync::oneshot,
+        time::{delay_for, interval, timeout},
+    },
     uuid::Uuid,
 };
 
+#[cfg(feature = "protobuf")]
+use crate::proto::{self, ProtoEvent};
+
 /// `tokio` runtime wrapper for spawning async Echo Events
-#[derive(Debug)]
+///
+/// A `Spawner` is cheap to clone, so it can be cloned into each producer thread.  Clones
+/// share one runtime, HTTP client, and connection pool, along with the session counters,
+/// host limits, pause state, event sequence, default url, bearer token, and logger.  The
+/// rest of the configuration is copied, so setters called on a clone affect only that
+/// clone.
+///
+/// Dropping the last clone without a `shutdown` abandons any sends still in flight,
+/// with a warning through the logger set by `set_logger`.
+#[derive(Clone, Debug, Setters)]
 pub struct Spawner {
-    /// The `tokio` runtime
-    rt: Runtime,
+    /// Warns about abandoned sends when the last clone is dropped.  Declared before the
+    /// runtime so it's dropped while the abandoned sends are still counted.
+    abandon_warning: Arc<AbandonWarning>,
+    /// The `tokio` runtime, shared by all clones
+    rt: Arc<Runtime>,
+    /// The spawner logger, for payloads without one
+    logger: Arc<RwLock<Option<Logger>>>,
     /// The `hyper` client
-    client: Client<HttpsConnector<HttpConnector>>,
+    client: HttpsClient,
+    /// The default data center, set on any event that doesn't have one
+    #[set = "pub"]
+    data_center: Option<String>,
+    /// The default host, set on any event that doesn't have one.  This defaults to the
+    /// local hostname, resolved once at construction.  Override it where the hostname
+    /// isn't meaningful, i.e. a container id.
+    #[set = "pub"]
+    default_host: Option<String>,
+    /// The tenant id stamped on every event, see `Spawner::for_tenant`
+    tenant_id: Option<String>,
+    /// The maximum number of `message_detail` keys sent per event.  Any keys beyond
+    /// the cap are summarized into a single `libechoexec.truncated_keys` entry, a cap of
+    /// 0 drops the `message_detail` entirely.
+    #[set = "pub"]
+    max_detail_keys: Option<usize>,
+    /// The `slog` level used to log each send outcome
+    #[set = "pub"]
+    log_levels: LogLevels,
+    /// The casing convention used for serialized field names
+    #[set = "pub"]
+    field_casing: FieldCasing,
+    /// How events with both client and destination fields set are handled
+    #[set = "pub"]
+    direction_check: Strictness,
+    /// How `response_code` or `response` fields on non-PERFORMANCE events are handled
+    #[set = "pub"]
+    response_field_check: Strictness,
+    /// Gzip request bodies when doing so makes them smaller
+    #[set = "pub"]
+    compression: bool,
+    /// Set the `timestamp` of any event without one to the spawn time.  Every event in a
+    /// batch gets the same timestamp.  Disable this to let the collector stamp events.
+    #[set = "pub"]
+    auto_timestamp: bool,
+    /// Assign one generated correlation id to every event in a payload without one, so
+    /// the whole batch can be joined downstream.  See `Spawner::spawn_correlated`.
+    #[set = "pub"]
+    auto_correlate: bool,
+    /// Stamp a monotonic `sequence` number on each event so a collector can restore
+    /// submission order when timestamps tie.  Whether the collector honors the field is
+    /// collector dependent.
+    #[set = "pub"]
+    sequence_events: bool,
+    /// The next event sequence number
+    sequence: Arc<AtomicU64>,
+    /// A file to read the `Authorization` bearer token from on each send
+    #[set = "pub"]
+    token_file: Option<TokenFile>,
+    /// Drop any event less severe than this before sending
+    #[set = "pub"]
+    min_severity: Option<EventType>,
+    /// Randomly drop a fraction of the events of each type before sending, i.e. to shed
+    /// high-volume INFO events under load
+    #[set = "pub"]
+    sampling: Option<SamplingPolicy>,
+    /// The session counters
+    stats: Arc<Stats>,
+    /// When the spawner was created
+    started: Instant,
+    /// Send a SYSTEM event summarizing the session on `shutdown`
+    #[set = "pub"]
+    shutdown_summary: Option<ShutdownSummary>,
+    /// Prepare and serialize payloads as usual, but log the body to the payload logger
+    /// rather than sending it, i.e. for debugging
+    #[set = "pub"]
+    dry_run: bool,
+    /// The number of events submitted, by event type and routing key
+    event_counts: Arc<EventCounts>,
+    /// How events with an empty routing key are handled
+    #[set = "pub"]
+    empty_routing_key: InvalidEventPolicy,
+    /// The per-host concurrent request limits
+    host_limits: Arc<HostLimits>,
+    /// The budget of in-flight request body bytes, `None` is unlimited
+    byte_budget: Option<Arc<ByteBudget>>,
+    /// The request rate limit, `None` is unlimited
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// What to do with sends over the rate limit
+    rate_limit_policy: RateLimitPolicy,
+    /// The collector circuit breaker, `None` is disabled
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// The `User-Agent` sent with each request
+    user_agent: String,
+    /// The path of the collector single-event endpoint, i.e. `/echo/message`.  When set,
+    /// single-event JSON payloads are sent to this path, on the payload collector host,
+    /// as a bare JSON object rather than an array.  Multi-event payloads are still sent
+    /// to the batch endpoint.
+    #[set = "pub"]
+    single_event_path: Option<String>,
+    /// What to do with payloads spawned while paused
+    #[set = "pub"]
+    pause_policy: PausePolicy,
+    /// The maximum number of payloads buffered while paused, 1024 by default.  Once full,
+    /// the oldest buffered payloads are dropped, and their events counted as
+    /// `paused_dropped`.  `None` buffers without limit.
+    #[set = "pub"]
+    pause_buffer_capacity: Option<usize>,
+    /// The paused state, and the payloads buffered while paused
+    pause_gate: Arc<PauseGate>,
+    /// A hard cap on the serialized payload size in bytes, 5MB by default.  Payloads
+    /// over the cap fail with `ErrKind::PayloadTooLarge` before a request is built, rather
+    /// than being rejected by the collector with a 413.
+    #[set = "pub"]
+    max_payload_bytes: Option<usize>,
+    /// Wrap the serialized events array in a root object
+    #[set = "pub"]
+    root_wrapper: Option<RootWrapper>,
+    /// Remove events with identical fingerprints from each batch, keeping the first
+    #[set = "pub"]
+    dedup_batches: bool,
+    /// Fail any payload that doesn't pass `Payload::validate` before it is sent
+    #[set = "pub"]
+    strict_validation: bool,
+    /// Fail any payload with a SYSTEM event with `ErrKind::ReservedEventType`, keeping
+    /// application code from writing to the system index.  Off by default.
+    #[set = "pub"]
+    reject_system_events: bool,
+    /// Whether this is the internal spawner allowed to send SYSTEM events
+    system_sender: bool,
+    /// Send events protobuf encoded (`application/x-protobuf`) rather than as JSON
+    #[cfg(feature = "protobuf")]
+    #[set = "pub"]
+    protobuf: bool,
+    /// Retry only the events the collector rejected when it rejects part of a batch,
+    /// rather than dropping the batch.  The rejected subset is retried once, after the
+    /// jittered `retry_backoff` delay.
+    #[set = "pub"]
+    retry_rejected: bool,
+    /// The base retry backoff delay, doubled on each attempt and jittered
+    #[set = "pub"]
+    retry_backoff: Duration,
+    /// The longest `Retry-After` delay honored, 30 seconds by default.  Longer delays
+    /// asked for by the collector are clamped to it, so a misbehaving collector can't
+    /// stall a retrying send indefinitely.
+    #[set = "pub"]
+    max_retry_after: Duration,
+    /// The source of randomness used to jitter retry backoff delays
+    #[set = "pub"]
+    jitter: Arc<dyn Jitter>,
+    /// The sink for per-request send metrics, `NoopMetrics` by default
+    #[set = "pub"]
+    metrics: Arc<dyn Metrics>,
+    /// How long to wait for the collector to respond to a request before giving up with
+    /// `ErrKind::Timeout`
+    #[set = "pub"]
+    timeout: Duration,
+    /// How long to spend reading a response body before giving up
+    #[set = "pub"]
+    read_body_timeout: Duration,
+    /// The most bytes of an error response body kept, 64KB by default.  Longer bodies
+    /// are truncated, though still read to the end so the connection can be reused.
+    #[set = "pub"]
+    max_error_body_bytes: usize,
+    /// Send an `Idempotency-Key` header with each request, the SHA-256 hash of the
+    /// request body, so the collector can discard duplicates.  The retries of `spawn`
+    /// and `send_with_retry` resend the same body, and so the same key, even with
+    /// `auto_timestamp` or `sequence_events` set.  Resending just the rejected events of
+    /// a batch (`retry_rejected`) sends a new body, with a new key.
+    #[set = "pub"]
+    idempotency: bool,
+    /// A collector url that overrides the payload urls, swappable at runtime
+    default_url: Arc<RwLock<Option<CollectorUrl>>>,
+    /// The `Authorization` bearer token, swappable at runtime
+    bearer_token: Arc<RwLock<Option<Redacted<String>>>>,
+    /// The spawned sends that haven't completed, drained on `shutdown`
+    in_flight: Arc<InFlight>,
 }
 
 impl Spawner {
@@ -42,537 +279,5331 @@ impl Spawner {
     /// # Errors
     ///
     pub fn new() -> crate::error::Result<Self> {
-        // Setup the shared HTTP(S) client
-        let mut http = HttpConnector::new();
-        http.enforce_http(false);
+        Self::with_config(&SpawnerConfig::default())
+    }
 
-        let mut tls_builder = TlsConnector::builder();
-        // yay fucking self-signed certs
-        let _ = tls_builder.danger_accept_invalid_certs(true);
-        let tls = tls_builder.build()?;
+    /// Create a new `EchoRuntime` that spawns onto the given pre-built `tokio` runtime.
+    /// Use this when you need full control over the runtime configuration (worker
+    /// count, thread names, on-thread-start hooks, etc.).
+    ///
+    /// # Errors
+    ///
+    pub fn with_runtime(rt: Runtime) -> crate::error::Result<Self> {
+        let config = SpawnerConfig::default();
+        let client = tls::client(&config)?;
+        Ok(Self::with_client(rt, client, &config))
+    }
 
-        let https = HttpsConnector::from((http, tls.into()));
-        let client = Client::builder().build::<_, Body>(https);
-        let rt = Runtime::new()?;
+    /// Create a new `EchoRuntime` with the given settings.  `Spawner::new` uses the
+    /// `SpawnerConfig` defaults.
+    ///
+    /// # Errors
+    ///
+    pub fn with_config(config: &SpawnerConfig) -> crate::error::Result<Self> {
+        let client = tls::client(config)?;
+        Ok(Self::with_client(Runtime::new()?, client, config))
+    }
 
-        Ok(Self { rt, client })
+    /// Create a new `EchoRuntime` that presents a client certificate to the collector,
+    /// for collectors requiring mutual TLS.  Some environments require this to send to
+    /// the `Prod` `CollectorUrl`.
+    ///
+    /// Shorthand for `with_config` with `SpawnerConfig::set_identity`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::NativeTLS` if the PKCS #12 archive can't be decrypted with
+    /// `password`, or the TLS connector can't be built.
+    #[cfg(feature = "tls")]
+    pub fn with_identity(pkcs12_der: &[u8], password: &str) -> crate::error::Result<Self> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_identity(pkcs12_der, password);
+        Self::with_config(&config)
     }
 
-    /// Spawn an `Echo Event` on the inner `tokio` runtime
+    /// Create a new `EchoRuntime` that presents a PEM encoded client certificate chain
+    /// and PKCS #8 private key to the collector, for collectors requiring mutual TLS.
+    /// Unlike `with_identity`, this works with both the `tls` and `rustls` backends.
+    /// Shorthand for `with_config` with `SpawnerConfig::set_pem_identity`.
     ///
     /// # Errors
     ///
-    pub fn spawn(&self, payload: &Payload) -> crate::error::Result<()> {
-        // Clone to move into async closure
-        let events_clone = payload.events.clone();
-        let client = self.client.clone();
-        let logger = payload.logger.clone();
+    /// Returns an error if the certificate chain or private key can't be parsed, or the
+    /// TLS connector can't be built.
+    pub fn with_pem_identity(cert_chain_pem: &[u8], key_pem: &[u8]) -> crate::error::Result<Self> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_pem_identity(cert_chain_pem, key_pem);
+        Self::with_config(&config)
+    }
 
-        // Setup some other pre-reqs
-        let uri = payload.url.as_str().to_string();
-        let json = serde_json::to_string(&events_clone)?;
+    fn with_client(rt: Runtime, client: HttpsClient, config: &SpawnerConfig) -> Self {
+        let in_flight = Arc::new(InFlight::default());
+        let logger = Arc::new(RwLock::new(config.logger().cloned()));
+        let data_center = config
+            .data_center_resolver()
+            .and_then(|resolver| {
+                let client = client.clone();
+                rt.enter(|| block_on(metadata::resolve(client, resolver)))
+            })
+            .or_else(|| config.data_center().cloned());
 
-        let _ = self.rt.spawn(async {
-            let _res = run_impl(client, logger, uri, json).await;
-        });
+        Self {
+            abandon_warning: Arc::new(AbandonWarning::new(in_flight.clone(), logger.clone())),
+            logger,
+            rt: Arc::new(rt),
+            client,
+            data_center,
+            default_host: hostname::get()
+                .ok()
+                .and_then(|host| host.into_string().ok()),
+            tenant_id: config.tenant_id().cloned(),
+            max_detail_keys: None,
+            log_levels: LogLevels::default(),
+            field_casing: FieldCasing::default(),
+            direction_check: Strictness::default(),
+            response_field_check: Strictness::default(),
+            compression: false,
+            auto_timestamp: true,
+            auto_correlate: false,
+            sequence_events: false,
+            sequence: Arc::new(AtomicU64::new(0)),
+            token_file: None,
+            min_severity: None,
+            sampling: None,
+            stats: Arc::new(Stats::default()),
+            started: Instant::now(),
+            shutdown_summary: None,
+            dry_run: false,
+            event_counts: Arc::new(EventCounts::default()),
+            empty_routing_key: InvalidEventPolicy::default(),
+            host_limits: Arc::new(HostLimits::default()),
+            byte_budget: None,
+            rate_limiter: config
+                .max_requests_per_sec()
+                .map(|per_sec| Arc::new(RateLimiter::new(per_sec))),
+            rate_limit_policy: config.rate_limit_policy(),
+            circuit_breaker: config.circuit_breaker().map(Arc::new),
+            user_agent: config.user_agent().map_or_else(
+                || USER_AGENT.clone(),
+                |app| format!("{} {}", app, *USER_AGENT),
+            ),
+            pause_policy: PausePolicy::default(),
+            pause_buffer_capacity: Some(DEFAULT_PAUSE_BUFFER_CAPACITY),
+            pause_gate: Arc::new(PauseGate::default()),
+            max_payload_bytes: Some(DEFAULT_MAX_PAYLOAD_BYTES),
+            single_event_path: None,
+            root_wrapper: None,
+            default_url: Arc::new(RwLock::new(config.default_url().cloned())),
+            bearer_token: Arc::new(RwLock::new(config.bearer_token().cloned())),
+            in_flight,
+            dedup_batches: false,
+            strict_validation: false,
+            reject_system_events: false,
+            system_sender: false,
+            retry_rejected: false,
+            retry_backoff: Duration::from_millis(100),
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+            jitter: Arc::new(ThreadRngJitter),
+            metrics: Arc::new(NoopMetrics),
+            #[cfg(feature = "protobuf")]
+            protobuf: false,
+            timeout: Duration::from_secs(30),
+            read_body_timeout: Duration::from_secs(5),
+            max_error_body_bytes: DEFAULT_MAX_ERROR_BODY_BYTES,
+            idempotency: false,
+        }
+    }
 
-        Ok(())
+    /// Create a new `EchoRuntime` for a multi-tenant platform.  The tenant id is stamped
+    /// on every event sent, replacing any tenant id already set on the event, so events
+    /// can't be sent without it or on behalf of another tenant.  Shorthand for
+    /// `with_config` with `SpawnerConfig::set_tenant_id`.
+    ///
+    /// # Errors
+    ///
+    pub fn for_tenant<T>(tenant_id: T) -> crate::error::Result<Self>
+    where
+        T: Into<String>,
+    {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_tenant_id(Some(tenant_id.into()));
+        Self::with_config(&config)
     }
-}
 
-// A simple type alias so as to DRY.
-type FutResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+    /// Create a new `EchoRuntime` that authenticates with the collector by sending
+    /// `Authorization: Bearer <token>` on every request.  Use `set_token` to rotate
+    /// the token.  Shorthand for `with_config` with `SpawnerConfig::set_bearer_token`.
+    ///
+    /// # Errors
+    ///
+    pub fn with_bearer_token<T>(token: T) -> crate::error::Result<Self>
+    where
+        T: Into<String>,
+    {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_bearer_token(token);
+        Self::with_config(&config)
+    }
 
-lazy_static! {
-    static ref USER_AGENT: String =
-        format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-}
+    /// Create a new `EchoRuntime` that logs through `logger` whenever a payload has no
+    /// logger of its own.  See `set_logger`.  Shorthand for `with_config` with
+    /// `SpawnerConfig::set_logger`.
+    ///
+    /// # Errors
+    ///
+    pub fn with_logger(logger: Logger) -> crate::error::Result<Self> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_logger(Some(logger));
+        Self::with_config(&config)
+    }
 
-async fn run_impl(
-    client: Client<HttpsConnector<HttpConnector>>,
-    logger: Option<Logger>,
-    url: String,
-    json: String,
-) -> FutResult<()> {
-    let length = json.as_bytes().len();
+    /// Create a new `EchoRuntime` that sends every payload to the collector configured in
+    /// the environment, see `CollectorUrl::from_env`.  Shorthand for `with_config` with
+    /// `SpawnerConfig::set_default_url`.
+    ///
+    /// # Errors
+    ///
+    pub fn from_env() -> crate::error::Result<Self> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_default_url(Some(CollectorUrl::from_env()?));
+        Self::with_config(&config)
+    }
 
-    let req = Request::builder()
-        .method("POST")
-        .uri(url)
-        .header("User-Agent", (*USER_AGENT).clone())
-        .header("Content-Type", "application/json")
-        .header("Content-Length", length)
-        .body(Body::from(json))?;
+    /// Cap the number of concurrent requests to each collector host.  Each host is
+    /// limited independently, so a slow host doesn't starve requests to the others.
+    /// `None` (the default) is unlimited, a limit of 0 is raised to 1.
+    pub fn set_max_connections_per_host(&mut self, limit: Option<usize>) -> &mut Self {
+        self.host_limits = Arc::new(HostLimits::new(limit));
+        self
+    }
 
-    let mut resp = client.request(req).await?;
+    /// Cap the total bytes of request bodies in flight at once, bounding memory under
+    /// high concurrency.  Sends wait for budget proportional to their body size before
+    /// sending, independently of any connection limits.  `None` (the default) is
+    /// unlimited.
+    ///
+    /// With a budget, bodies are encoded only once budget for their estimated size, the
+    /// events serialized as a JSON array, is acquired, so bodies waiting on the budget
+    /// aren't held in memory.  Errors encoding a spawned body, i.e.
+    /// `ErrKind::PayloadTooLarge`, are then logged when it's sent rather than returned by
+    /// `spawn`.
+    pub fn set_max_in_flight_bytes(&mut self, max_bytes: Option<usize>) -> &mut Self {
+        self.byte_budget = max_bytes.map(|max_bytes| Arc::new(ByteBudget::new(max_bytes)));
+        self
+    }
 
-    if resp.status().is_success() {
-        try_trace!(logger, "Successfully sent payload to echo");
-        Ok(())
-    } else {
-        let err_type = if resp.status().is_client_error() {
-            "Client"
-        } else if resp.status().is_server_error() {
-            "Server"
-        } else {
-            "Unknown"
-        };
+    /// The number of requests the `max_requests_per_sec` rate limit allows right now, or
+    /// `None` if the rate is unlimited
+    #[must_use]
+    pub fn available_requests(&self) -> Option<u32> {
+        self.rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.available())
+    }
 
-        try_error!(
-            logger,
-            "{} error sending Echo Payload: {}",
-            err_type,
-            resp.status()
-        );
+    /// Render the body this spawner would send for the payload, after all of the
+    /// filtering, validation, and enrichment applied by `spawn`, without sending it.
+    /// Protobuf bodies are rendered lossily.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload can't be prepared or serialized.
+    pub fn render(&self, payload: &Payload) -> crate::error::Result<String> {
+        let events = payload.prepare_events(self)?;
+        let (_, content, _) = payload.encode(self, &events)?;
+        Ok(String::from_utf8_lossy(&content).into_owned())
+    }
 
-        let mut buffer = vec![];
-        while let Some(next) = resp.data().await {
-            let chunk = next?;
-            buffer.write_all(&chunk)?;
+    /// Log the serialized events instead of sending them when `dry_run` is set,
+    /// returning whether they were logged
+    fn log_dry_run(&self, payload: &Payload, events: &[Event]) -> crate::error::Result<bool> {
+        if self.dry_run {
+            let (uri, content, _) = payload.encode(self, events)?;
+            try_info!(
+                self.logger_for(payload),
+                "Dry run Echo Payload to {}: {}",
+                uri,
+                String::from_utf8_lossy(&content)
+            );
         }
+        Ok(self.dry_run)
+    }
 
-        try_error!(logger, "{}", String::from_utf8_lossy(&buffer));
-        Err(ErrKind::Run.into())
+    /// The state of the collector circuit breaker.  This is always `Closed` if the
+    /// breaker is disabled.
+    #[must_use]
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker
+            .as_ref()
+            .map_or(CircuitState::Closed, |breaker| breaker.state())
     }
-}
 
-/// The Echo messages urls
-#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
-pub enum CollectorUrl {
-    /// The stage url (https://echocollector-stage.kroger.com/echo/messages)
-    Stage,
-    /// The prod url (https://echocollector.kroger.com/echo/messages)
-    Prod,
-}
+    /// Fail a send immediately while the circuit breaker is open
+    fn check_circuit(&self) -> crate::error::Result<()> {
+        match &self.circuit_breaker {
+            Some(breaker) if !breaker.try_acquire() => Err(ErrKind::CircuitOpen.into()),
+            _ => Ok(()),
+        }
+    }
 
-impl Default for CollectorUrl {
-    fn default() -> Self {
-        Self::Stage
+    /// Apply the rate limit to a send.  Under `Reject`, a token is taken now.  Under
+    /// `Delay`, the limiter to wait on before sending is returned.
+    fn rate_limit(&self) -> crate::error::Result<Option<Arc<RateLimiter>>> {
+        match &self.rate_limiter {
+            Some(limiter) if self.rate_limit_policy == RateLimitPolicy::Reject => {
+                if limiter.try_acquire() {
+                    Ok(None)
+                } else {
+                    Err(ErrKind::RateLimited.into())
+                }
+            }
+            limiter => Ok(limiter.clone()),
+        }
     }
-}
 
-impl CollectorUrl {
-    /// Convert the enum to a str
+    /// The number of events dropped by the `min_severity` filter
     #[must_use]
-    pub fn as_str(self) -> &'static str {
-        match self {
-            Self::Stage => "https://echocollector-stage.kroger.com/echo/messages",
-            Self::Prod => "https://echocollector.kroger.com/echo/messages",
-        }
+    pub fn filtered(&self) -> u64 {
+        self.stats.filtered.load(Ordering::Relaxed)
     }
-}
 
-/// The payload for sending a batch of Echo `Event`s
-#[derive(Clone, Debug, Default, Setters)]
-pub struct Payload {
-    /// The collector url to use
-    #[set = "pub"]
-    url: CollectorUrl,
-    /// The batch of events to send
-    #[set = "pub"]
-    events: Vec<Event>,
-    /// An optional `slog` logger
-    #[set = "pub"]
-    logger: Option<Logger>,
-    /// An error count for retries, this is not serialized.
-    error_count: usize,
-    /// The retry count if an error occurred sending the batch
-    retry_count: usize,
-}
+    /// The number of events dropped by the `sampling` policy
+    #[must_use]
+    pub fn sampled(&self) -> u64 {
+        self.stats.sampled.load(Ordering::Relaxed)
+    }
 
-/// An Echo Event
-#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, Setters)]
-pub struct Event {
-    /// The routing_key is what identifies the message with an application. It will become the ElasticSearch index.
-    /// Valid characters are lowercase alpha numeric and '-'.
-    /// The key should follow the format <application group>-<application name>-<environment>.
-    #[serde(rename = "routingKey")]
-    routing_key: String,
-    /// Echo Event Type
-    #[serde(rename = "type")]
-    #[set = "pub"]
-    event_type: EventType,
-    /// A simple string message.  Most messages should be one line of information.  If you have secondary, deeper information to store, put it in the `message_detail`.
-    ///
-    /// This field holds the data when the tail appender or default log appender is used.
-    message: String,
-    /// The correlation id
-    #[set = "pub"]
-    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
-    correlation_id: Option<Uuid>,
-    /// The timestamp of the event.  If unset, it will be set by the EchoClient.
-    ///
-    /// If producing your own messages, the format of the date should be either of:
+    /// Pause sending, i.e. during a collector maintenance window.  Payloads spawned while
+    /// paused are buffered in memory, up to the `pause_buffer_capacity`, or dropped,
+    /// according to the `pause_policy`.
+    pub fn pause(&self) {
+        self.pause_gate.pause();
+    }
+
+    /// Resume sending, spawning any payloads buffered while paused
     ///
-    /// * An ISO-8601 date/time string (e.g. 2017-04-06T17:23:00-04:00)
-    /// * A number representing milliseconds since epoch (e.g. 1491514054000)
+    /// # Errors
     ///
-    #[set = "pub"]
-    #[serde(skip_serializing_if = "Option::is_none")]
-    timestamp: Option<i64>,
-    /// A place to store custom key/value pairs in the message, typically used when there isn't an appropriate root-level field.
-    #[set = "pub"]
-    #[serde(rename = "messageDetail", skip_serializing_if = "Option::is_none")]
-    message_detail: Option<HashMap<String, String>>,
-    /// Hostname where the message originated. If None, it will be set by the EchoClient.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    host: Option<String>,
-    /// Sets the version of the application that is creating this message.
-    #[serde(rename = "applicationVersion", skip_serializing_if = "Option::is_none")]
-    application_version: Option<String>,
-    /// Sets the datacenter that the application is in, based on DCPloy environment settings.
-    #[serde(rename = "dataCenter", skip_serializing_if = "Option::is_none")]
-    data_center: Option<String>,
-    /// The hostname of a client if this message is involving an external system calling into your system.
-    #[serde(rename = "clientHostName", skip_serializing_if = "Option::is_none")]
-    client_host_name: Option<String>,
-    /// The hostname of a destination system if this message is involving your system calling an external system.
-    #[serde(
-        rename = "destinationHostName",
-        skip_serializing_if = "Option::is_none"
-    )]
-    destination_host_name: Option<String>,
-    /// The path being called on a destination system if this message is involving your system calling an external system.
-    #[serde(rename = "destinationPath", skip_serializing_if = "Option::is_none")]
-    destination_path: Option<String>,
-    /// Sets the timestamp of millis since the epoch for the time at which this event started.
-    #[serde(rename = "startTimestamp", skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    start_timestamp: Option<u64>,
-    /// Sets the timestamp of millis since the epoch for the time at which this event finished.
-    #[serde(rename = "finishTimestamp", skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    finish_timestamp: Option<u64>,
-    /// Sets the duration (time in milliseconds) that passed during this event.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    duration: Option<u64>,
-    /// Sets the duration (time in milliseconds) that passed during this event.
-    #[serde(rename = "durationInMs", skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    duration_in_ms: Option<u64>,
-    /// The HTTP response code returned by a performance event.
-    #[serde(rename = "responseCode", skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    response_code: Option<u16>,
-    /// A more generic response used when a HTTP response code doesn't make sense. Typical values might be "success" or "failure".
-    #[serde(skip_serializing_if = "Option::is_none")]
-    #[set = "pub"]
-    response: Option<Response>,
-}
+    /// Returns the first error spawning a buffered payload.  The remaining buffered
+    /// payloads are still spawned.
+    pub fn resume(&self) -> crate::error::Result<()> {
+        let mut result = Ok(());
 
-impl Event {
-    /// Set the routing key field
-    pub fn set_routing_key<T>(&mut self, routing_key: T) -> &mut Self
-    where
-        T: Into<String>,
-    {
-        self.routing_key = routing_key.into();
-        self
+        for payload in self.pause_gate.resume() {
+            if let Err(e) = self.spawn(&payload) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
     }
 
-    /// Set the message field
-    pub fn set_message<T>(&mut self, message: T) -> &mut Self
-    where
-        T: Into<String>,
-    {
-        self.message = message.into();
-        self
+    /// Whether sending is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.pause_gate.is_paused()
     }
 
-    /// Set the host field
-    pub fn set_host<T>(&mut self, host: Option<T>) -> &mut Self
-    where
-        T: Into<String>,
-    {
-        self.host = match host {
-            None => None,
-            Some(t) => Some(t.into()),
-        };
-        self
+    /// The number of events buffered while paused
+    #[must_use]
+    pub fn paused_buffered(&self) -> u64 {
+        self.stats.paused_buffered.load(Ordering::Relaxed)
     }
 
-    /// Set the application version field
-    pub fn set_application_version<T>(&mut self, application_version: Option<T>) -> &mut Self
+    /// The number of events dropped while paused, including those dropped from a full
+    /// pause buffer
+    #[must_use]
+    pub fn paused_dropped(&self) -> u64 {
+        self.stats.paused_dropped.load(Ordering::Relaxed)
+    }
+
+    /// The total bytes successfully sent to the collector, both as serialized JSON and
+    /// as sent on the wire after any compression
+    #[must_use]
+    pub fn bytes_sent(&self) -> BytesSent {
+        self.stats.bytes_sent()
+    }
+
+    /// The counts of events submitted by this spawner, by event type and routing key,
+    /// in OpenMetrics text exposition format.  Serve this from a scrape endpoint for a
+    /// pull-based summary of activity.
+    #[must_use]
+    pub fn metrics_text(&self) -> String {
+        self.event_counts.render(&self.stats)
+    }
+
+    /// Create a new `EchoRuntime`, resolving the default data center from a cloud
+    /// metadata service.  If the metadata service can't be reached or doesn't respond
+    /// quickly, `fallback` is used instead.  Shorthand for `with_config` with
+    /// `SpawnerConfig::set_data_center_resolver` and `SpawnerConfig::set_data_center`.
+    ///
+    /// # Errors
+    ///
+    pub fn with_data_center_resolver<R>(
+        resolver: R,
+        fallback: Option<String>,
+    ) -> crate::error::Result<Self>
     where
-        T: Into<String>,
+        R: DataCenterResolver + 'static,
     {
-        self.application_version = match application_version {
-            None => None,
-            Some(t) => Some(t.into()),
-        };
-        self
+        let mut config = SpawnerConfig::default();
+        let _ = config
+            .set_data_center_resolver(resolver)
+            .set_data_center(fallback);
+        Self::with_config(&config)
     }
 
-    /// Set the datacenter field
-    pub fn set_data_center<T>(&mut self, data_center: Option<T>) -> &mut Self
+    /// Spawn an `Echo Event` on the inner `tokio` runtime
+    ///
+    /// # Errors
+    ///
+    pub fn spawn(&self, payload: &Payload) -> crate::error::Result<()> {
+        self.spawn_correlated(payload).map(|_| ())
+    }
+
+    /// Spawn events bound for different collectors, partitioning them by the url
+    /// returned by `route` and spawning one payload per distinct url.  The events keep
+    /// their relative order within each payload.
+    ///
+    /// # Errors
+    ///
+    /// Every payload is spawned, and the first error spawning a payload is returned.
+    pub fn spawn_grouped<F>(&self, events: Vec<Event>, route: F) -> crate::error::Result<()>
     where
-        T: Into<String>,
+        F: Fn(&Event) -> CollectorUrl,
     {
-        self.data_center = match data_center {
-            None => None,
-            Some(t) => Some(t.into()),
+        group_by_url(events, route)
+            .iter()
+            .map(|payload| self.spawn(payload))
+            .fold(Ok(()), Result::and)
+    }
+
+    /// Spawn a single `Echo Event` on the inner `tokio` runtime, in a default payload.
+    /// Use `spawn` for batches, or to configure the payload.
+    ///
+    /// # Errors
+    ///
+    pub fn spawn_event(&self, event: Event) -> crate::error::Result<()> {
+        self.spawn_event_to(CollectorUrl::default(), event)
+    }
+
+    /// Spawn a single `Echo Event` to the given collector url on the inner `tokio`
+    /// runtime, in an otherwise default payload
+    ///
+    /// # Errors
+    ///
+    pub fn spawn_event_to(&self, url: CollectorUrl, event: Event) -> crate::error::Result<()> {
+        let mut payload = Payload::default();
+        let _ = payload.set_url(url).set_events(vec![event]);
+        self.spawn(&payload)
+    }
+
+    /// Spawn an `Echo Event` on the inner `tokio` runtime, returning the correlation id
+    /// `auto_correlate` assigned to the payload events without one.  This is `None` if
+    /// `auto_correlate` is disabled, every event already had a correlation id, or the
+    /// payload was held by a paused spawner.
+    ///
+    /// # Errors
+    ///
+    pub fn spawn_correlated(&self, payload: &Payload) -> crate::error::Result<Option<Uuid>> {
+        let prepared = match self.prepare_send(payload)? {
+            Prepared::Ready(prepared) => *prepared,
+            Prepared::Skipped(correlation_id) => return Ok(correlation_id),
         };
-        self
+        let correlation_id = prepared.correlation_id;
+        let body_config = if self.retry_rejected && prepared.events.len() > 1 {
+            Some(self.body_config(payload.format))
+        } else {
+            None
+        };
+        let mut delivery = Delivery {
+            client: self.client.clone(),
+            logger: prepared.logger.clone(),
+            config: self.run_config(),
+            stats: self.stats.clone(),
+            error_count: payload.error_count,
+            retry_count: payload.retry_count,
+            partial_retry: None,
+        };
+
+        let _ = self.rt.spawn(async move {
+            let PreparedSend {
+                _in_flight,
+                limits,
+                req,
+                events,
+                ..
+            } = prepared;
+            let _permits = limits.acquire(req.budget()).await;
+            let (stats, logger, levels) = (
+                delivery.stats.clone(),
+                delivery.logger.clone(),
+                delivery.config.levels,
+            );
+            let delivered = match req.encode(&events) {
+                Ok((req, size)) => {
+                    let count = events.len();
+                    delivery.partial_retry = body_config.map(|body_config| PartialRetry {
+                        body_config,
+                        events,
+                    });
+                    delivery.deliver(req, size, count).await
+                }
+                Err(e) => {
+                    try_warn!(logger, "Error encoding Echo Payload: {}", e);
+                    false
+                }
+            };
+
+            if !delivered {
+                Stats::add(&stats.failed, 1);
+                levels.log(
+                    &logger,
+                    Outcome::Drop,
+                    format_args!("Dropping Echo Payload"),
+                );
+            }
+        });
+
+        Ok(correlation_id)
     }
 
-    /// Set the client host name
-    pub fn set_client_host_name<T>(&mut self, client_host_name: Option<T>) -> &mut Self
+    /// Send a payload, blocking until the collector responds, and return the result of
+    /// the send.  Unlike `spawn`, the payload is sent once, without retries.  Like
+    /// `spawn`, a payload sent while the spawner is paused is held according to the
+    /// `pause_policy`, and `Ok` is returned without waiting for it to be sent.
+    ///
+    /// This blocks the calling thread, so it must be called from a synchronous context,
+    /// never from within an async task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload can't be prepared or sent, including
+    /// `ErrKind::Run` with the HTTP status and response body when the collector
+    /// responds with an error.
+    pub fn send(&self, payload: &Payload) -> crate::error::Result<()> {
+        match self.prepare_send(payload)? {
+            Prepared::Ready(prepared) => {
+                let send = prepared.send(
+                    self.client.clone(),
+                    self.run_config(),
+                    self.stats.clone(),
+                    0,
+                );
+                block_on(self.rt.spawn(send)).map_err(|e| e.to_string())?
+            }
+            Prepared::Skipped(_) => Ok(()),
+        }
+    }
+
+    /// Send a payload like `send`, retrying connection errors, timeouts, and server error
+    /// (5xx) responses up to the payload `retry_count` times if set, otherwise
+    /// `max_retries` times, with jittered exponential backoff from `backoff`.  Client
+    /// error (4xx) responses, other than `429 Too Many Requests`, fail immediately.
+    ///
+    /// The payload is prepared once, and every attempt resends the same request, so the
+    /// events keep their correlation ids, sequence numbers, and timestamps.
+    ///
+    /// This blocks the calling thread, so it must be called from a synchronous context,
+    /// never from within an async task.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the last attempt, or any error preparing the payload.
+    pub fn send_with_retry(
+        &self,
+        payload: &Payload,
+        max_retries: usize,
+        backoff: Duration,
+    ) -> crate::error::Result<()> {
+        let retries = if payload.retry_count > 0 {
+            payload.retry_count
+        } else {
+            max_retries
+        };
+
+        match self.prepare_send(payload)? {
+            Prepared::Ready(prepared) => {
+                let mut config = self.run_config();
+                config.retry_backoff = backoff;
+                let send = prepared.send(self.client.clone(), config, self.stats.clone(), retries);
+                block_on(self.rt.spawn(send)).map_err(|e| e.to_string())?
+            }
+            Prepared::Skipped(_) => Ok(()),
+        }
+    }
+
+    /// Build the request for a payload, returning a future that sends it once when
+    /// awaited, rather than spawning it on the inner runtime.  Use this from within your
+    /// own async application, to compose sends with `select!`, `join!`, etc.  The future
+    /// must be driven within a `tokio` runtime.
+    ///
+    /// Errors preparing the payload, including `ErrKind::ShutDown`, are returned when the
+    /// future is awaited.
+    pub fn request(&self, payload: &Payload) -> impl Future<Output = crate::error::Result<()>> {
+        let prepared = self.prepare_send(payload);
+        let client = self.client.clone();
+        let config = self.run_config();
+        let stats = self.stats.clone();
+
+        async move {
+            match prepared? {
+                Prepared::Ready(prepared) => prepared.send(client, config, stats, 0).await,
+                Prepared::Skipped(_) => Ok(()),
+            }
+        }
+    }
+
+    /// Send many payloads once each, i.e. to drain a backlog at startup, with at most
+    /// `concurrency` requests in flight at a time.  The returned future resolves to the
+    /// result of each send, in the order of `payloads`.  Each payload is only prepared
+    /// when its send starts, so at most `concurrency` request bodies are held at once.
+    /// Like `request`, the future must be driven within a `tokio` runtime.
+    pub fn spawn_all(
+        &self,
+        payloads: Vec<Payload>,
+        concurrency: usize,
+    ) -> impl Future<Output = Vec<crate::error::Result<()>>> {
+        let spawner = self.clone();
+
+        async move {
+            stream::iter(payloads)
+                .map(|payload| spawner.request(&payload))
+                .buffered(concurrency.max(1))
+                .collect()
+                .await
+        }
+    }
+
+    /// A `Sink` that sends each payload once, in order, from a task on the inner runtime,
+    /// buffering about `buffer` payloads.  When the buffer is full, the sink isn't ready
+    /// until a send completes, propagating backpressure to the producer rather than
+    /// piling up spawned tasks without bound.
+    ///
+    /// Errors preparing a payload, including `ErrKind::ShutDown`, are returned when the
+    /// payload is sent to the sink.  Errors sending it to the collector are logged and
+    /// counted, as with `spawn`.
+    pub fn sink(&self, buffer: usize) -> impl Sink<Payload, Error = crate::error::Err> {
+        let (tx, mut rx) = mpsc::channel::<Prepared>(buffer);
+        let client = self.client.clone();
+        let config = self.run_config();
+        let stats = self.stats.clone();
+
+        let _ = self.rt.spawn(async move {
+            while let Some(prepared) = rx.next().await {
+                if let Prepared::Ready(prepared) = prepared {
+                    let _ = prepared
+                        .send(client.clone(), config.clone(), stats.clone(), 0)
+                        .await;
+                }
+            }
+        });
+
+        let spawner = self.clone();
+        tx.sink_map_err(|_| crate::error::Err::from(ErrKind::WorkerStopped))
+            .with(move |payload: Payload| future::ready(spawner.prepare_send(&payload)))
+    }
+
+    /// Prepare a payload to send, applying the checks shared by every send path, i.e.
+    /// the shutdown and pause gates, the payload preparation, dry runs, the circuit
+    /// breaker, and a rejecting rate limit.  The prepared send waits on the rest of its
+    /// `SendLimits` before it's sent.
+    fn prepare_send(&self, payload: &Payload) -> crate::error::Result<Prepared> {
+        let in_flight = self.in_flight.enter().ok_or(ErrKind::ShutDown)?;
+
+        if let Some((action, evicted)) =
+            self.pause_gate
+                .hold(payload, self.pause_policy, self.pause_buffer_capacity)
+        {
+            let counter = match action {
+                PausePolicy::Buffer => &self.stats.paused_buffered,
+                PausePolicy::Drop => &self.stats.paused_dropped,
+            };
+            Stats::add(counter, payload.events.len());
+            Stats::add(&self.stats.paused_dropped, evicted);
+            return Ok(Prepared::Skipped(None));
+        }
+
+        let (events, origins, correlation_id) = payload.prepare_correlated(self)?;
+
+        if events.is_empty() {
+            return Ok(Prepared::Skipped(None));
+        }
+
+        if self.log_dry_run(payload, &events)? {
+            return Ok(Prepared::Skipped(correlation_id));
+        }
+
+        self.check_circuit()?;
+        let rate_limiter = self.rate_limit()?;
+        let (uri, encoder) = payload.body_encoder(self, &events)?;
+        let template = payload.request_template(self, &uri)?;
+        let host_limit = template
+            .uri
+            .host()
+            .and_then(|host| self.host_limits.semaphore(host));
+        let req = if self.byte_budget.is_some() {
+            PendingRequest::Deferred(template, encoder, body::json_len(&events)?)
+        } else {
+            let (req, size) = encoder.request(&template, &events)?;
+            PendingRequest::Encoded(req, size)
+        };
+        self.event_counts.record(
+            events
+                .iter()
+                .map(|e| (e.event_type.as_str(), e.routing_key.as_str())),
+        );
+
+        Ok(Prepared::Ready(Box::new(PreparedSend {
+            _in_flight: in_flight,
+            limits: SendLimits {
+                rate_limiter,
+                byte_budget: self.byte_budget.clone(),
+                host_limit,
+            },
+            logger: self.logger_for(payload),
+            req,
+            events,
+            origins,
+            correlation_id,
+        })))
+    }
+
+    /// Spawn an `Echo Event` on the inner `tokio` runtime, returning a channel that
+    /// receives the collector acknowledgement, including any per-event rejections.  The
+    /// acknowledgement is parsed from both success and error responses, so partial
+    /// rejections that would otherwise look like full successes can be detected.
+    ///
+    /// The payload is sent once, without retries.  The channel is closed without an
+    /// acknowledgement if no events are sent, the payload is held by a paused spawner,
+    /// the send fails before the collector responds, or the response body isn't an
+    /// acknowledgement.  An empty success body is acknowledged with an empty `BatchAck`.
+    /// A success body of ordered per-event acks, i.e. `[{"id":"a"}]`, is correlated with
+    /// the events sent, after any `Spawner` filtering and `auto_correlate`, see
+    /// `BatchAck::events`.
+    /// The acknowledgement carries the exact response status, i.e. `202 Accepted` vs
+    /// `200 OK`, and, when `idempotency` is set, the `Idempotency-Key` sent.  The
+    /// rejected indices are those of the payload events, before any `Spawner` filtering.
+    ///
+    /// # Errors
+    ///
+    pub fn spawn_with_response(
+        &self,
+        payload: &Payload,
+    ) -> crate::error::Result<oneshot::Receiver<BatchAck>> {
+        let (tx, rx) = oneshot::channel();
+        let prepared = match self.prepare_send(payload)? {
+            Prepared::Ready(prepared) => *prepared,
+            Prepared::Skipped(_) => return Ok(rx),
+        };
+        let client = self.client.clone();
+        let config = self.run_config();
+        let stats = self.stats.clone();
+
+        let _ = self.rt.spawn(async move {
+            let PreparedSend {
+                _in_flight,
+                limits,
+                logger,
+                req,
+                events,
+                origins,
+                ..
+            } = prepared;
+            let _permits = limits.acquire(req.budget()).await;
+            let (req, size) = match req.encode(&events) {
+                Ok(encoded) => encoded,
+                Err(e) => {
+                    Stats::add(&stats.failed, 1);
+                    try_warn!(logger, "Error encoding Echo Payload: {}", e);
+                    return;
+                }
+            };
+            let idempotency_key = req.idempotency_key();
+            let (levels, count) = (config.levels, events.len());
+            let ack = match run_request(client, logger.clone(), config, &req).await {
+                Ok((status, body)) => {
+                    Stats::add(&stats.sent, count);
+                    stats.add_bytes(size);
+
+                    if body.iter().all(u8::is_ascii_whitespace) {
+                        Some(BatchAck::default())
+                    } else if let Ok(acks) = serde_json::from_slice::<Vec<CollectorAck>>(&body) {
+                        let accepted = acks.len();
+                        Some(BatchAck::with_events(
+                            accepted,
+                            correlate_acks(&events, acks, &logger),
+                        ))
+                    } else {
+                        serde_json::from_slice::<BatchAck>(&body).ok()
+                    }
+                    .map(|mut ack| {
+                        ack.set_status(status.as_u16());
+                        ack
+                    })
+                }
+                Err(e) => {
+                    Stats::add(&stats.failed, 1);
+                    levels.log(
+                        &logger,
+                        Outcome::Drop,
+                        format_args!("Dropping Echo Payload"),
+                    );
+
+                    match e.downcast::<ErrKind>().map(|kind| *kind) {
+                        Ok(ErrKind::Rejected(rejection)) => Some(rejection.ack().clone()),
+                        Ok(ErrKind::Run { status, body, .. }) => {
+                            serde_json::from_str(&body).ok().map(|mut ack: BatchAck| {
+                                ack.set_status(status);
+                                ack
+                            })
+                        }
+                        _ => None,
+                    }
+                }
+            };
+
+            if let Some(mut ack) = ack {
+                ack.map_indices(&origins);
+                ack.set_idempotency_key(idempotency_key);
+                let _ = tx.send(ack);
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// A clone of the spawner allowed to send SYSTEM events, for the events the library
+    /// sends itself.  The user filters (`min_severity`, `sampling`, `dedup_batches`)
+    /// are meant for application events, so they're skipped.
+    fn system_spawner(&self) -> Self {
+        let mut spawner = self.clone();
+        spawner.system_sender = true;
+        spawner.min_severity = None;
+        spawner.sampling = None;
+        spawner.dedup_batches = false;
+        spawner
+    }
+
+    /// The inner `tokio` runtime
+    pub(crate) fn rt(&self) -> &Runtime {
+        &self.rt
+    }
+
+    /// A handle to the inner `tokio` runtime, for spawning your own tasks, i.e. token
+    /// refresh or metric flushes, without running a second runtime.  Tasks spawned with
+    /// the handle share the spawner's thread pool with the echo sends, and are stopped
+    /// when the runtime shuts down.
+    #[must_use]
+    pub fn handle(&self) -> Handle {
+        self.rt.handle().clone()
+    }
+
+    /// The cap on the serialized payload size in bytes
+    pub(crate) fn max_payload_bytes(&self) -> Option<usize> {
+        self.max_payload_bytes
+    }
+
+    fn body_config(&self, format: PayloadFormat) -> BodyConfig {
+        BodyConfig {
+            format,
+            field_casing: self.field_casing,
+            root_wrapper: self.root_wrapper.clone(),
+            #[cfg(feature = "protobuf")]
+            protobuf: self.protobuf,
+            compression: self.compression,
+        }
+    }
+
+    fn run_config(&self) -> RunConfig {
+        RunConfig {
+            levels: self.log_levels,
+            retry_backoff: self.retry_backoff,
+            max_retry_after: self.max_retry_after,
+            timeout: self.timeout,
+            read_body_timeout: self.read_body_timeout,
+            max_error_body_bytes: self.max_error_body_bytes,
+            jitter: self.jitter.clone(),
+            metrics: self.metrics.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+        }
+    }
+
+    /// Redirect all subsequent sends to `url`, overriding the payload urls, without
+    /// otherwise reconfiguring the spawner (i.e. during a collector migration).
+    ///
+    /// The url is swapped under a lock, so every spawn that starts after this returns,
+    /// from any thread, sees the new url.  Requests already built by in-flight spawns
+    /// keep the url they were built with.
+    pub fn set_default_url(&self, url: CollectorUrl) {
+        if let Ok(mut default_url) = self.default_url.write() {
+            *default_url = Some(url);
+        }
+    }
+
+    /// Set the spawner logger, shared by all clones.  It's used for payloads without a
+    /// logger of their own, and for warnings that don't concern a single payload, i.e.
+    /// sends abandoned by dropping the last clone of the spawner without a `shutdown`.
+    pub fn set_logger(&self, logger: Option<Logger>) {
+        if let Ok(mut current) = self.logger.write() {
+            *current = logger;
+        }
+    }
+
+    /// The logger for a payload, its own logger if it has one, otherwise the spawner
+    /// logger, if any
+    pub(crate) fn logger_for(&self, payload: &Payload) -> Option<Logger> {
+        payload
+            .logger
+            .clone()
+            .or_else(|| self.logger.read().ok().and_then(|logger| logger.clone()))
+    }
+
+    /// Replace the `Authorization` bearer token.  Every request built after this call,
+    /// from any thread, uses the new token.  Requests already built by in-flight spawns
+    /// keep the token they were built with.
+    pub fn set_token<T>(&self, token: T)
     where
         T: Into<String>,
     {
-        self.client_host_name = match client_host_name {
-            None => None,
-            Some(t) => Some(t.into()),
+        if let Ok(mut bearer_token) = self.bearer_token.write() {
+            *bearer_token = Some(Redacted(token.into()));
+        }
+    }
+
+    /// The current `Authorization` bearer token, if one has been set
+    fn bearer_token(&self) -> Option<String> {
+        self.bearer_token
+            .read()
+            .ok()
+            .and_then(|token| token.as_ref().map(|token| token.0.clone()))
+    }
+
+    /// The `Authorization` header, from the `token_file` or the bearer token, if any
+    fn authorization(&self) -> crate::error::Result<Option<HeaderValue>> {
+        let token = match &self.token_file {
+            Some(token_file) => Some(token_file.token()?),
+            None => self.bearer_token(),
         };
-        self
+
+        token
+            .map(|token| {
+                HeaderValue::from_str(&format!("Bearer {}", token))
+                    .map_err(|e| hyper::http::Error::from(e).into())
+            })
+            .transpose()
+    }
+
+    /// Check the collector is reachable before sending, with a `HEAD` request carrying
+    /// the spawner `User-Agent` and `Authorization`, returning whether it responded with
+    /// a success (2xx) status.  This exercises the TLS and auth configuration without
+    /// sending any events.
+    ///
+    /// This blocks the calling thread, so it must be called from a synchronous context,
+    /// never from within an async task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request can't be built or sent, including
+    /// `ErrKind::Timeout` if the collector doesn't respond within the spawner timeout.
+    pub fn ping(&self, url: CollectorUrl) -> crate::error::Result<bool> {
+        let mut builder =
+            Request::head(&*url.as_str()).header("User-Agent", self.user_agent.as_str());
+
+        if let Some(authorization) = self.authorization()? {
+            builder = builder.header(AUTHORIZATION, authorization);
+        }
+        let req = builder.body(Body::empty())?;
+        let client = self.client.clone();
+        let wait = self.timeout;
+
+        let resp = block_on(self.rt.spawn(timeout(wait, client.request(req))))
+            .map_err(|e| e.to_string())?
+            .map_err(|_| ErrKind::Timeout)??;
+        Ok(resp.status().is_success())
+    }
+
+    /// Establish a pooled connection to the collector at startup, so the first real send
+    /// doesn't pay the cold connection (TCP + TLS handshake) cost.  This sends a `HEAD`
+    /// request, like `ping`, whatever the response status.
+    ///
+    /// The connection is only reused if `hyper` keeps it pooled, so this depends on the
+    /// `SpawnerConfig` `pool_max_idle_per_host` being non-zero, and the first send coming
+    /// within the `pool_idle_timeout`.  See `keep_warm` to keep a connection warm.
+    ///
+    /// This blocks the calling thread, so it must be called from a synchronous context,
+    /// never from within an async task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection can't be established, see `ping`.
+    pub fn warm_up(&self, url: CollectorUrl) -> crate::error::Result<()> {
+        self.ping(url).map(|_| ())
+    }
+
+    /// The collector url overriding the payload urls, if one has been set
+    #[must_use]
+    pub fn default_url(&self) -> Option<CollectorUrl> {
+        self.default_url.read().ok().and_then(|url| url.clone())
+    }
+
+    /// Keep a pooled connection to a standby collector warm by sending it a `HEAD`
+    /// request every `period`, so failing over to it doesn't pay the cold connection
+    /// (TCP + TLS handshake) cost.  This relies on `hyper` keeping idle connections
+    /// pooled for at least `period`.  The requests stop once `shutdown` is called on any
+    /// clone of the spawner.
+    ///
+    /// # Errors
+    ///
+    pub fn keep_warm(&self, url: CollectorUrl, period: Duration) -> crate::error::Result<()> {
+        let client = self.client.clone();
+        let uri: hyper::Uri = url.as_str().parse().map_err(hyper::http::Error::from)?;
+        let user_agent = self.user_agent.clone();
+        let in_flight = self.in_flight.clone();
+
+        let _ = self.rt.spawn(async move {
+            let mut ticks = interval(period);
+
+            loop {
+                let _ = ticks.tick().await;
+
+                if in_flight.is_closed() {
+                    break;
+                }
+
+                if let Ok(req) = Request::head(uri.clone())
+                    .header("User-Agent", user_agent.as_str())
+                    .body(Body::empty())
+                {
+                    let _ = client.request(req).await;
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Shut down the spawner, draining in-flight sends.  New sends are refused with
+    /// `ErrKind::ShutDown`, then up to `grace` is spent waiting for the spawned sends to
+    /// complete before the inner `tokio` runtime is shut down.  Sends still in flight
+    /// when `grace` elapses are dropped.  Payloads buffered while paused are spawned
+    /// first, so they're drained with the rest.  If a `shutdown_summary` is configured,
+    /// a final SYSTEM event summarizing the session (events sent, failures, retries,
+    /// filtered events, and uptime) is sent once the sends have drained, within what's
+    /// left of the grace period.
+    ///
+    /// Clones share the runtime and in-flight sends, so shutting down any clone refuses
+    /// new sends on every clone, and the runtime is only shut down by the last clone.
+    ///
+    /// This blocks the calling thread, so it must be called from a synchronous context,
+    /// never from within an async task.
+    ///
+    /// # Errors
+    ///
+    pub fn shutdown(mut self, grace: Duration) -> crate::error::Result<()> {
+        let deadline = Instant::now() + grace;
+        let logger = self.logger.read().ok().and_then(|logger| logger.clone());
+
+        let buffered = self.pause_gate.resume();
+
+        if !buffered.is_empty() {
+            try_warn!(
+                logger,
+                "Spawning {} payloads buffered while paused before shutting down",
+                buffered.len()
+            );
+        }
+        for payload in buffered {
+            if let Err(e) = self.spawn(&payload) {
+                try_warn!(logger, "Error spawning a buffered Echo Payload: {}", e);
+            }
+        }
+
+        self.in_flight.close();
+        let _ = self.in_flight.wait(deadline);
+
+        if let Some(summary) = self.shutdown_summary.take() {
+            match self.summary_request(summary) {
+                Ok(Some(req)) => {
+                    let client = self.client.clone();
+                    let config = self.run_config();
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+
+                    let _ = block_on(
+                        self.rt
+                            .spawn(timeout(remaining, run_impl(client, None, config, req))),
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => try_warn!(logger, "Error building the shutdown summary: {}", e),
+            }
+        }
+
+        if let Ok(rt) = Arc::try_unwrap(self.rt) {
+            rt.shutdown_timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+
+    /// The request carrying the shutdown summary event, `None` if preparing the payload
+    /// left no event to send
+    fn summary_request(
+        &self,
+        summary: ShutdownSummary,
+    ) -> crate::error::Result<Option<Request<Body>>> {
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .set_routing_key(summary.routing_key)
+            .set_event_type(EventType::System)
+            .set_message("Echo spawner shutdown summary")
+            .set_message_detail(Some(self.stats.summary(self.started.elapsed())))
+            .set_timestamp_dt(Utc::now());
+
+        let mut payload = Payload::default();
+        let _ = payload.set_url(summary.url).set_events(vec![echo_event]);
+
+        let system_spawner = self.system_spawner();
+        let events = payload.prepare_events(&system_spawner)?;
+
+        if events.is_empty() {
+            return Ok(None);
+        }
+        payload
+            .build_request(&system_spawner, &events)?
+            .0
+            .request()
+            .map(Some)
+    }
+}
+
+/// The headers describing the request body, which payload headers can't override
+const RESERVED_HEADERS: [HeaderName; 3] = [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING];
+
+/// Whether the routing key only contains lowercase alphanumerics and '-', and is of the
+/// form `<application group>-<application name>-<environment>`
+fn is_valid_routing_key(routing_key: &str) -> bool {
+    let valid_chars = routing_key
+        .chars()
+        .all(|ch| ch.is_ascii_lowercase() || ch.is_ascii_digit() || ch == '-');
+
+    valid_chars
+        && routing_key
+            .split('-')
+            .filter(|part| !part.is_empty())
+            .count()
+            >= 3
+}
+
+/// The default cap on the serialized payload size, the collector limit
+const DEFAULT_MAX_PAYLOAD_BYTES: usize = 5 * 1024 * 1024;
+
+/// The header carrying the idempotency key of a request
+const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+
+/// The default cap on the error response body kept
+const DEFAULT_MAX_ERROR_BODY_BYTES: usize = 64 * 1024;
+
+/// The default cap on the delay asked for by a `Retry-After` header
+const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// The cap on the success response body kept, well above any collector acknowledgement
+const MAX_ACK_BODY_BYTES: usize = 1024 * 1024;
+
+/// Appended to error response bodies truncated at the cap
+const TRUNCATED_BODY: &str = "... (truncated)";
+
+/// The `message_detail` key used to summarize truncated keys, namespaced so it can't
+/// collide with an application key
+const TRUNCATED_KEYS: &str = "libechoexec.truncated_keys";
+
+/// The `Spawner` configuration used while sending a request
+#[derive(Clone, Debug)]
+struct RunConfig {
+    /// The `slog` level used to log each send outcome
+    levels: LogLevels,
+    /// How long to wait for the collector to respond
+    timeout: Duration,
+    /// How long to spend reading a response body
+    read_body_timeout: Duration,
+    /// The most bytes of an error response body kept
+    max_error_body_bytes: usize,
+    /// The base retry backoff delay
+    retry_backoff: Duration,
+    /// The longest `Retry-After` delay honored
+    max_retry_after: Duration,
+    /// The source of randomness used to jitter retry backoff delays
+    jitter: Arc<dyn Jitter>,
+    /// The sink for per-request send metrics
+    metrics: Arc<dyn Metrics>,
+    /// The collector circuit breaker, if enabled
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+}
+
+impl RunConfig {
+    /// Record whether the collector handled a send with the circuit breaker
+    fn record_outcome(&self, handled: bool) {
+        if let Some(breaker) = &self.circuit_breaker {
+            if handled {
+                breaker.record_success();
+            } else {
+                breaker.record_failure();
+            }
+        }
+    }
+}
+
+// A simple type alias so as to DRY.
+type FutResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+lazy_static! {
+    static ref USER_AGENT: String =
+        format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+}
+
+async fn run_impl(
+    client: HttpsClient,
+    logger: Option<Logger>,
+    config: RunConfig,
+    req: Request<Body>,
+) -> FutResult<(StatusCode, Vec<u8>)> {
+    let levels = config.levels;
+    let uri = req.uri().clone();
+    let bytes = req
+        .body()
+        .size_hint()
+        .exact()
+        .map_or(0, |bytes| usize::try_from(bytes).unwrap_or(usize::MAX));
+    let start = Instant::now();
+    let mut resp = match timeout(config.timeout, client.request(req)).await {
+        Ok(Ok(resp)) => resp,
+        Ok(Err(e)) => {
+            config.metrics.on_failure(None);
+            config.record_outcome(false);
+            levels.log(
+                &logger,
+                Outcome::ServerError,
+                format_args!("Error sending Echo Payload to {}: {}", uri, e),
+            );
+            return Err(e.into());
+        }
+        Err(_) => {
+            config.metrics.on_failure(None);
+            config.record_outcome(false);
+            levels.log(
+                &logger,
+                Outcome::ServerError,
+                format_args!(
+                    "Timed out sending Echo Payload to {} after {}ms",
+                    uri,
+                    config.timeout.as_millis()
+                ),
+            );
+            return Err(ErrKind::Timeout.into());
+        }
+    };
+    let status = resp.status();
+    config.record_outcome(!status.is_server_error());
+    let retry_after =
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            resp.headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| backoff::retry_after(value, Utc::now()))
+        } else {
+            None
+        };
+
+    // Success bodies are acknowledgements, so they get a cap that fits any of those
+    let cap = if status.is_success() {
+        MAX_ACK_BODY_BYTES
+    } else {
+        config.max_error_body_bytes
+    };
+    let mut buffer = vec![];
+    let mut truncated = false;
+    let read = timeout(config.read_body_timeout, async {
+        while let Some(next) = resp.data().await {
+            let chunk = next?;
+            let room = cap.saturating_sub(buffer.len());
+
+            if chunk.len() > room {
+                truncated = true;
+            }
+            buffer.write_all(&chunk[..chunk.len().min(room)])?;
+        }
+        FutResult::Ok(())
+    })
+    .await;
+
+    if status.is_success() {
+        config.metrics.on_success(start.elapsed(), bytes);
+        levels.log(
+            &logger,
+            Outcome::Success,
+            format_args!("Successfully sent payload to echo"),
+        );
+        try_trace!(logger, "Echo collector responded {}", status);
+
+        if truncated {
+            try_warn!(
+                logger,
+                "Discarded the acknowledgement body past {} bytes",
+                MAX_ACK_BODY_BYTES
+            );
+        }
+        Ok((status, buffer))
+    } else {
+        config.metrics.on_failure(Some(status.as_u16()));
+        let (description, outcome) = if status.is_client_error() {
+            ("Client error", Outcome::ClientError)
+        } else if status.is_server_error() {
+            ("Server error", Outcome::ServerError)
+        } else {
+            ("Unexpected status", Outcome::UnexpectedStatus)
+        };
+
+        levels.log(
+            &logger,
+            outcome,
+            format_args!(
+                "{} sending Echo Payload to {}: {}",
+                description, uri, status
+            ),
+        );
+
+        let mut body = String::from_utf8_lossy(&buffer).into_owned();
+
+        if truncated {
+            body.push_str(TRUNCATED_BODY);
+        }
+
+        match read {
+            Ok(result) => {
+                result?;
+                levels.log(&logger, outcome, format_args!("{}", body));
+
+                if let Ok(mut ack) = serde_json::from_slice::<BatchAck>(&buffer) {
+                    ack.set_status(status.as_u16());
+
+                    if !ack.rejected().is_empty() {
+                        return Err(ErrKind::Rejected(ack.into()).into());
+                    }
+                }
+            }
+            Err(_) => levels.log(
+                &logger,
+                outcome,
+                format_args!(
+                    "Timed out reading the error body after {} bytes: {}",
+                    buffer.len(),
+                    body
+                ),
+            ),
+        }
+        Err(ErrKind::Run {
+            status: status.as_u16(),
+            body,
+            retry_after,
+        }
+        .into())
+    }
+}
+
+/// A payload prepared by `Spawner::prepare_send`
+#[derive(Debug)]
+enum Prepared {
+    /// There is nothing to send, the payload was held by a paused spawner, had no events
+    /// left to send, or was logged by a dry run.  Holds any correlation id assigned.
+    Skipped(Option<Uuid>),
+    /// The payload is ready to send
+    Ready(Box<PreparedSend>),
+}
+
+/// A payload prepared to send, and the limits it waits on before it's sent
+#[derive(Debug)]
+struct PreparedSend {
+    /// Holds the send in-flight until it completes
+    _in_flight: InFlightGuard,
+    /// The limits to wait on before sending
+    limits: SendLimits,
+    /// The payload logger
+    logger: Option<Logger>,
+    /// The request
+    req: PendingRequest,
+    /// The prepared events in the request
+    events: Vec<Event>,
+    /// The index in the payload of each prepared event, as the `Spawner` may filter some
+    origins: Vec<usize>,
+    /// The correlation id assigned by `auto_correlate`, if any
+    correlation_id: Option<Uuid>,
+}
+
+impl PreparedSend {
+    /// Wait for the send limits, then send the request, resending the same request after
+    /// transient errors up to `retries` times with jittered exponential backoff from the
+    /// `retry_backoff`.  Updates the stats with the result.
+    async fn send(
+        self,
+        client: HttpsClient,
+        config: RunConfig,
+        stats: Arc<Stats>,
+        retries: usize,
+    ) -> crate::error::Result<()> {
+        let _permits = self.limits.acquire(self.req.budget()).await;
+        let (req, size) = match self.req.encode(&self.events) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                Stats::add(&stats.failed, 1);
+                return Err(e);
+            }
+        };
+        let mut attempt = 0;
+
+        loop {
+            match run_request(client.clone(), self.logger.clone(), config.clone(), &req).await {
+                Ok(_) => {
+                    Stats::add(&stats.sent, self.events.len());
+                    stats.add_bytes(size);
+                    return Ok(());
+                }
+                Err(e) if attempt < retries && is_transient(&*e) => {
+                    Stats::add(&stats.retried, 1);
+                    let exponent = u32::try_from(attempt).unwrap_or(u32::max_value());
+                    let wait = e
+                        .downcast_ref::<ErrKind>()
+                        .and_then(|kind| retry_delay(kind, config.max_retry_after))
+                        .unwrap_or_else(|| {
+                            backoff::delay(&*config.jitter, config.retry_backoff, exponent)
+                        });
+                    delay_for(wait).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    Stats::add(&stats.failed, 1);
+
+                    // The rejected indices refer to the prepared events
+                    return Err(match e.downcast::<ErrKind>().map(|kind| *kind) {
+                        Ok(ErrKind::Rejected(mut rejection)) => {
+                            rejection.map_indices(&self.origins);
+                            ErrKind::Rejected(rejection).into()
+                        }
+                        Ok(kind) => kind.into(),
+                        Err(e) => e.into(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// A collector request, encoded, or to be encoded once the byte budget is acquired
+#[derive(Debug)]
+enum PendingRequest {
+    /// The encoded request and its body size
+    Encoded(CollectorRequest, BodySize),
+    /// The request without a body, the encoder of its body, and the estimated body size
+    Deferred(CollectorRequest, BodyEncoder, usize),
+}
+
+impl PendingRequest {
+    /// The body size the byte budget is acquired for, estimated if the body isn't
+    /// encoded yet
+    fn budget(&self) -> usize {
+        match self {
+            Self::Encoded(_, size) => size.encoded,
+            Self::Deferred(_, _, estimate) => *estimate,
+        }
+    }
+
+    /// The encoded request and its body size, encoding the events if they aren't
+    /// encoded yet
+    fn encode(&self, events: &[Event]) -> crate::error::Result<(CollectorRequest, BodySize)> {
+        match self {
+            Self::Encoded(req, size) => Ok((req.clone(), *size)),
+            Self::Deferred(template, encoder, _) => encoder.request(template, events),
+        }
+    }
+}
+
+/// Send a collector request once
+async fn run_request(
+    client: HttpsClient,
+    logger: Option<Logger>,
+    config: RunConfig,
+    req: &CollectorRequest,
+) -> FutResult<(StatusCode, Vec<u8>)> {
+    run_impl(client, logger, config, req.request()?).await
+}
+
+/// The `Spawner` configuration used to encode events into a request body
+#[derive(Clone, Debug)]
+struct BodyConfig {
+    /// The framing of the JSON events
+    format: PayloadFormat,
+    /// The casing of the serialized JSON field names
+    field_casing: FieldCasing,
+    /// Wraps the JSON events array in a root object
+    root_wrapper: Option<RootWrapper>,
+    /// Encode the events as protobuf rather than JSON
+    #[cfg(feature = "protobuf")]
+    protobuf: bool,
+    /// Gzip the body when that makes it smaller
+    compression: bool,
+}
+
+impl BodyConfig {
+    /// Whether events are encoded as JSON
+    fn is_json(&self) -> bool {
+        #[cfg(feature = "protobuf")]
+        {
+            !self.protobuf
+        }
+        #[cfg(not(feature = "protobuf"))]
+        {
+            true
+        }
+    }
+
+    /// Encode the events, returning the encoded bytes and their `Content-Type`.  JSON
+    /// events are serialized one at a time, so an event that can't be serialized is
+    /// dropped, with a warning, rather than failing the batch.
+    fn encode(
+        &self,
+        events: &[Event],
+        logger: &Option<Logger>,
+    ) -> crate::error::Result<(Vec<u8>, &'static str)> {
+        #[cfg(feature = "protobuf")]
+        {
+            if self.protobuf {
+                let events = events.iter().map(ProtoEvent::from).collect();
+                return Ok((proto::encode(events)?, proto::CONTENT_TYPE));
+            }
+        }
+
+        let (serialized, skipped) = self.field_casing.serialize_each(events);
+
+        for (idx, e) in skipped {
+            try_warn!(logger, "Dropping unserializable event {}: {}", idx, e);
+        }
+
+        if self.format == PayloadFormat::Ndjson {
+            let mut ndjson = String::new();
+
+            for event in serialized {
+                ndjson.push_str(&event);
+                ndjson.push('\n');
+            }
+            return Ok((ndjson.into_bytes(), "application/x-ndjson"));
+        }
+
+        let mut json = format!("[{}]", serialized.join(","));
+
+        if let Some(wrapper) = &self.root_wrapper {
+            json = wrapper.wrap(&json)?;
+        }
+        Ok((json.into_bytes(), "application/json"))
+    }
+}
+
+/// Encodes the prepared events of a request into its body
+#[derive(Clone, Debug)]
+struct BodyEncoder {
+    /// The `Spawner` configuration used to encode the events
+    config: BodyConfig,
+    /// Encode a single JSON event as a bare object, for the `single_event_path`
+    single_event: bool,
+    /// The cap on the encoded size, before compression
+    max_payload_bytes: Option<usize>,
+    /// The logger warned about events that can't be serialized
+    logger: Option<Logger>,
+}
+
+impl BodyEncoder {
+    /// Encode the events, returning the body before compression and its `Content-Type`
+    fn encode(&self, events: &[Event]) -> crate::error::Result<(Vec<u8>, &'static str)> {
+        if let Some(limit) = self.max_payload_bytes {
+            // The JSON events alone, counted without buffering them, are a lower bound
+            // on the body size, so an oversized batch fails before it's built
+            if self.config.is_json() {
+                let size = events
+                    .iter()
+                    .filter_map(|event| body::json_len(event).ok())
+                    .sum();
+
+                if size > limit {
+                    return Err(ErrKind::PayloadTooLarge { size, limit }.into());
+                }
+            }
+        }
+
+        let (content, content_type) = match events {
+            [event] if self.single_event => {
+                let json = self.config.field_casing.serialize_event(event)?;
+                (json.into_bytes(), "application/json")
+            }
+            _ => self.config.encode(events, &self.logger)?,
+        };
+
+        if let Some(limit) = self.max_payload_bytes {
+            if content.len() > limit {
+                return Err(ErrKind::PayloadTooLarge {
+                    size: content.len(),
+                    limit,
+                }
+                .into());
+            }
+        }
+        Ok((content, content_type))
+    }
+
+    /// Encode the events into the body of the request template
+    fn request(
+        &self,
+        template: &CollectorRequest,
+        events: &[Event],
+    ) -> crate::error::Result<(CollectorRequest, BodySize)> {
+        let (content, content_type) = self.encode(events)?;
+        let uncompressed = content.len();
+        let body = body::prepare(content, self.config.compression)?;
+        let size = body.size(uncompressed);
+        Ok((template.with_body(content_type, body), size))
+    }
+}
+
+/// Replace the path of the given url
+fn with_path(url: &str, path: &str) -> crate::error::Result<String> {
+    let mut parts = url
+        .parse::<hyper::Uri>()
+        .map_err(hyper::http::Error::from)?
+        .into_parts();
+    parts.path_and_query = Some(path.parse().map_err(hyper::http::Error::from)?);
+    Ok(hyper::Uri::from_parts(parts)
+        .map_err(hyper::http::Error::from)?
+        .to_string())
+}
+
+/// A collector `POST` request that can be sent more than once
+#[derive(Clone, Debug)]
+struct CollectorRequest {
+    /// The collector uri
+    uri: hyper::Uri,
+    /// The `Content-Type` of the body
+    content_type: &'static str,
+    /// The `Content-Encoding` of the body, if any
+    encoding: Option<&'static str>,
+    /// Additional headers, i.e. `Authorization`
+    headers: HeaderMap,
+    /// The body bytes
+    body: Bytes,
+    /// Send an `Idempotency-Key` header derived from the body
+    idempotency: bool,
+    /// The `User-Agent` header
+    user_agent: String,
+}
+
+impl CollectorRequest {
+    fn new(
+        uri: &str,
+        content_type: &'static str,
+        body: PreparedBody,
+        headers: HeaderMap,
+        spawner: &Spawner,
+    ) -> crate::error::Result<Self> {
+        Ok(Self {
+            uri: uri.parse().map_err(hyper::http::Error::from)?,
+            content_type,
+            encoding: body.encoding,
+            headers,
+            body: Bytes::from(body.bytes),
+            idempotency: spawner.idempotency,
+            user_agent: spawner.user_agent.clone(),
+        })
+    }
+
+    /// A copy of this request with a different body
+    fn with_body(&self, content_type: &'static str, body: PreparedBody) -> Self {
+        Self {
+            uri: self.uri.clone(),
+            content_type,
+            encoding: body.encoding,
+            headers: self.headers.clone(),
+            body: Bytes::from(body.bytes),
+            idempotency: self.idempotency,
+            user_agent: self.user_agent.clone(),
+        }
+    }
+
+    /// The `Idempotency-Key` sent with the request, if `idempotency` is set
+    fn idempotency_key(&self) -> Option<String> {
+        if self.idempotency {
+            Some(body::idempotency_key(&self.body))
+        } else {
+            None
+        }
+    }
+
+    /// Build the `hyper` request
+    fn request(&self) -> crate::error::Result<Request<Body>> {
+        let mut builder = Request::builder()
+            .method("POST")
+            .uri(self.uri.clone())
+            .header("User-Agent", self.user_agent.as_str())
+            .header("Content-Type", self.content_type)
+            .header("Content-Length", self.body.len());
+
+        if let Some(encoding) = self.encoding {
+            builder = builder.header("Content-Encoding", encoding);
+        }
+
+        if let Some(key) = self.idempotency_key() {
+            builder = builder.header(IDEMPOTENCY_KEY, key);
+        }
+
+        let mut req = builder.body(Body::from(self.body.clone()))?;
+
+        for (name, value) in &self.headers {
+            let _ = req.headers_mut().insert(name.clone(), value.clone());
+        }
+        Ok(req)
+    }
+}
+
+/// What's needed to resend the events of a partially rejected batch
+#[derive(Debug)]
+struct PartialRetry {
+    /// The body configuration of the original request
+    body_config: BodyConfig,
+    /// The prepared events of the original request
+    events: Vec<Event>,
+}
+
+impl PartialRetry {
+    /// Build the request resending the given subset of the events
+    fn request(
+        &self,
+        original: &CollectorRequest,
+        subset: &[Event],
+        logger: &Option<Logger>,
+    ) -> crate::error::Result<(CollectorRequest, BodySize)> {
+        let (content, content_type) = self.body_config.encode(subset, logger)?;
+        let uncompressed = content.len();
+        let body = body::prepare(content, self.body_config.compression)?;
+        let size = body.size(uncompressed);
+        Ok((original.with_body(content_type, body), size))
+    }
+}
+
+/// A spawned send of a payload, and its retry state
+struct Delivery {
+    /// The `hyper` client
+    client: HttpsClient,
+    /// The payload logger
+    logger: Option<Logger>,
+    /// The `Spawner` configuration used while sending
+    config: RunConfig,
+    /// The `Spawner` counters
+    stats: Arc<Stats>,
+    /// The number of errors sending the payload so far
+    error_count: usize,
+    /// The number of times the payload is retried after a transient error
+    retry_count: usize,
+    /// Resends just the rejected events of a partially rejected batch
+    partial_retry: Option<PartialRetry>,
+}
+
+impl Delivery {
+    /// Send the request of `count` events, retrying transient errors up to `retry_count`
+    /// times with exponential backoff.  Returns whether the events were delivered.
+    async fn deliver(mut self, req: CollectorRequest, size: BodySize, count: usize) -> bool {
+        loop {
+            let err = match self.send(&req).await {
+                Ok(()) => {
+                    Stats::add(&self.stats.sent, count);
+                    self.stats.add_bytes(size);
+                    return true;
+                }
+                Err(e) => e,
+            };
+
+            if let (Some(ErrKind::Rejected(rejection)), Some(retry)) =
+                (err.downcast_ref::<ErrKind>(), &self.partial_retry)
+            {
+                return self.resend_rejected(retry, &req, rejection, count).await;
+            }
+
+            if !is_transient(&*err) || self.error_count >= self.retry_count {
+                return false;
+            }
+
+            self.error_count += 1;
+            Stats::add(&self.stats.retried, 1);
+            self.config.levels.log(
+                &self.logger,
+                Outcome::Retry,
+                format_args!(
+                    "Retrying Echo Payload, attempt {} of {}",
+                    self.error_count, self.retry_count
+                ),
+            );
+            let attempt = u32::try_from(self.error_count - 1).unwrap_or(u32::max_value());
+            let wait = err
+                .downcast_ref::<ErrKind>()
+                .and_then(|kind| retry_delay(kind, self.config.max_retry_after))
+                .unwrap_or_else(|| {
+                    backoff::delay(&*self.config.jitter, self.config.retry_backoff, attempt)
+                });
+            delay_for(wait).await;
+        }
+    }
+
+    /// Resend just the rejected events of a partially rejected batch of `count` events
+    async fn resend_rejected(
+        &self,
+        retry: &PartialRetry,
+        original: &CollectorRequest,
+        rejection: &BatchRejection,
+        count: usize,
+    ) -> bool {
+        let subset = rejection.select(&retry.events);
+
+        if subset.is_empty() {
+            return false;
+        }
+
+        Stats::add(&self.stats.sent, count - subset.len());
+        Stats::add(&self.stats.retried, 1);
+        self.config.levels.log(
+            &self.logger,
+            Outcome::Retry,
+            format_args!(
+                "Retrying {} of {} Echo events rejected by the collector",
+                subset.len(),
+                count
+            ),
+        );
+        delay_for(backoff::delay(
+            &*self.config.jitter,
+            self.config.retry_backoff,
+            0,
+        ))
+        .await;
+
+        match retry.request(original, &subset, &self.logger) {
+            Ok((req, size)) if self.send(&req).await.is_ok() => {
+                Stats::add(&self.stats.sent, subset.len());
+                self.stats.add_bytes(size);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Send the request once
+    async fn send(&self, req: &CollectorRequest) -> FutResult<()> {
+        run_impl(
+            self.client.clone(),
+            self.logger.clone(),
+            self.config.clone(),
+            req.request()?,
+        )
+        .await
+        .map(|_| ())
+    }
+}
+
+/// Whether a send error is worth retrying, i.e. a connection error, a timeout, or a
+/// server error (5xx) or `429 Too Many Requests` response
+fn is_transient(err: &(dyn Error + Send + Sync + 'static)) -> bool {
+    match err.downcast_ref::<ErrKind>() {
+        Some(ErrKind::Hyper(_)) | Some(ErrKind::Timeout) => true,
+        Some(ErrKind::Run { status, .. }) => *status >= 500 || *status == 429,
+        Some(_) => false,
+        None => err.is::<hyper::Error>(),
+    }
+}
+
+/// Partition the events into one payload per url returned by `route`, in order of the
+/// first event routed to each url
+fn group_by_url<F>(events: Vec<Event>, route: F) -> Vec<Payload>
+where
+    F: Fn(&Event) -> CollectorUrl,
+{
+    let mut payloads: Vec<Payload> = vec![];
+
+    for event in events {
+        let url = route(&event);
+
+        match payloads.iter_mut().find(|payload| payload.url == url) {
+            Some(payload) => payload.events.push(event),
+            None => {
+                let mut payload = Payload::default();
+                let _ = payload.set_url(url).set_events(vec![event]);
+                payloads.push(payload);
+            }
+        }
+    }
+    payloads
+}
+
+/// Keep the events, along with their index in the payload, for which `keep` is true
+fn retain_indexed<F>(events: &mut Vec<Event>, origins: &mut Vec<usize>, mut keep: F)
+where
+    F: FnMut(&Event) -> bool,
+{
+    let (kept_origins, kept) = origins
+        .drain(..)
+        .zip(events.drain(..))
+        .filter(|(_, event)| keep(event))
+        .unzip();
+    *origins = kept_origins;
+    *events = kept;
+}
+
+/// Remove the events identical to an earlier event, keeping the first, along with
+/// their index in the payload, returning the number removed.  Events are bucketed by
+/// `fingerprint`, and only compared in full when their fingerprints match, so a hash
+/// collision never drops a distinct event.
+fn remove_duplicates<F>(events: &mut Vec<Event>, origins: &mut Vec<usize>, fingerprint: F) -> usize
+where
+    F: Fn(&Event) -> u64,
+{
+    let before = events.len();
+    let mut seen: HashMap<u64, Vec<Event>> = HashMap::new();
+    retain_indexed(events, origins, |event| {
+        let same = seen.entry(fingerprint(event)).or_default();
+        let duplicate = same.contains(event);
+
+        if !duplicate {
+            same.push(event.clone());
+        }
+        !duplicate
+    });
+    before - events.len()
+}
+
+/// The delay the collector asked for before retrying, if any, clamped to `max`
+fn retry_delay(kind: &ErrKind, max: Duration) -> Option<Duration> {
+    match kind {
+        ErrKind::Run { retry_after, .. } => retry_after.map(|delay| delay.min(max)),
+        _ => None,
+    }
+}
+
+/// The Echo messages urls
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum CollectorUrl {
+    /// The stage url (https://echocollector-stage.kroger.com/echo/messages)
+    Stage,
+    /// The prod url (https://echocollector.kroger.com/echo/messages)
+    Prod,
+    /// A custom url, i.e. a local mock collector (http://localhost:8080/echo/messages)
+    Custom(String),
+}
+
+impl Default for CollectorUrl {
+    fn default() -> Self {
+        Self::Stage
+    }
+}
+
+impl CollectorUrl {
+    /// Convert the enum to a str
+    #[must_use]
+    pub fn as_str(&self) -> Cow<'static, str> {
+        match self {
+            Self::Stage => Cow::Borrowed("https://echocollector-stage.kroger.com/echo/messages"),
+            Self::Prod => Cow::Borrowed("https://echocollector.kroger.com/echo/messages"),
+            Self::Custom(url) => Cow::Owned(url.clone()),
+        }
+    }
+
+    /// Read the collector url from the environment.  A url in `ECHO_COLLECTOR_URL` is
+    /// used as a `Custom` url, otherwise `ECHO_COLLECTOR_ENV` selects the `stage` or
+    /// `prod` collector.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::Var` if neither variable is set, or `ErrKind::Str` if
+    /// `ECHO_COLLECTOR_ENV` isn't `stage` or `prod`.
+    pub fn from_env() -> crate::error::Result<Self> {
+        Self::from_vars(
+            env::var("ECHO_COLLECTOR_URL").ok(),
+            env::var("ECHO_COLLECTOR_ENV"),
+        )
+    }
+
+    /// The collector url from the `ECHO_COLLECTOR_URL` and `ECHO_COLLECTOR_ENV` values
+    fn from_vars(
+        url: Option<String>,
+        collector_env: Result<String, VarError>,
+    ) -> crate::error::Result<Self> {
+        if let Some(url) = url.filter(|url| !url.trim().is_empty()) {
+            return Ok(Self::Custom(url.trim().to_string()));
+        }
+
+        let collector_env = collector_env?;
+        match collector_env.trim().to_lowercase().as_str() {
+            "stage" => Ok(Self::Stage),
+            "prod" => Ok(Self::Prod),
+            _ => Err(format!("invalid ECHO_COLLECTOR_ENV {:?}", collector_env)
+                .as_str()
+                .into()),
+        }
+    }
+}
+
+/// The payload for sending a batch of Echo `Event`s
+#[derive(Clone, Debug, Default, Setters)]
+pub struct Payload {
+    /// The collector url to use
+    #[set = "pub"]
+    url: CollectorUrl,
+    /// The batch of events to send
+    #[set = "pub"]
+    events: Vec<Event>,
+    /// An optional `slog` logger
+    #[set = "pub"]
+    logger: Option<Logger>,
+    /// Additional HTTP headers sent with the payload, i.e. `traceparent`.  The
+    /// `Content-Type`, `Content-Length`, and `Content-Encoding` headers are reserved, and
+    /// are ignored with a warning.
+    #[set = "pub"]
+    headers: HashMap<String, String>,
+    /// An error count for retries, this is not serialized.
+    error_count: usize,
+    /// The number of times the batch is retried, with exponential backoff, after a
+    /// server error (5xx) or `429` response, a timeout, or a connection error.  Defaults
+    /// to no retries.
+    #[set = "pub"]
+    retry_count: usize,
+    /// A template, i.e. `"{action} failed for {user}"`, used to generate the `message` of
+    /// any event with an empty message from its `message_detail` values.  Keys missing
+    /// from the `message_detail` are replaced with an empty string.
+    #[set = "pub"]
+    message_template: Option<String>,
+    /// The framing of the JSON events in the request body, a JSON array by default.
+    /// Ignored when the `Spawner` encodes events as protobuf.
+    #[set = "pub"]
+    format: PayloadFormat,
+}
+
+/// The serializable parts of a `Payload`, for persisting failed payloads, i.e. to a
+/// file-backed retry queue, and replaying them later.  The logger isn't persisted.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PersistablePayload {
+    /// The collector url to use
+    url: CollectorUrl,
+    /// The batch of events to send
+    events: Vec<Event>,
+    /// Additional HTTP headers sent with the payload
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// The number of errors sending the payload so far
+    #[serde(rename = "errorCount", default)]
+    error_count: usize,
+    /// The number of times the batch is retried after an error
+    #[serde(rename = "retryCount", default)]
+    retry_count: usize,
+    /// The template used to generate empty event messages
+    #[serde(rename = "messageTemplate", default)]
+    message_template: Option<String>,
+    /// The framing of the JSON events in the request body
+    #[serde(default)]
+    format: PayloadFormat,
+}
+
+impl From<&Payload> for PersistablePayload {
+    fn from(payload: &Payload) -> Self {
+        Self {
+            url: payload.url.clone(),
+            events: payload.events.clone(),
+            headers: payload.headers.clone(),
+            error_count: payload.error_count,
+            retry_count: payload.retry_count,
+            message_template: payload.message_template.clone(),
+            format: payload.format,
+        }
+    }
+}
+
+impl From<PersistablePayload> for Payload {
+    fn from(persisted: PersistablePayload) -> Self {
+        Self {
+            url: persisted.url,
+            events: persisted.events,
+            logger: None,
+            headers: persisted.headers,
+            error_count: persisted.error_count,
+            retry_count: persisted.retry_count,
+            message_template: persisted.message_template,
+            format: persisted.format,
+        }
+    }
+}
+
+impl Payload {
+    /// Append an event to the payload
+    pub fn add_event(&mut self, event: Event) -> &mut Self {
+        self.events.push(event);
+        self
+    }
+
+    /// Append events to the payload
+    pub fn extend_events<I>(&mut self, events: I) -> &mut Self
+    where
+        I: IntoIterator<Item = Event>,
+    {
+        self.events.extend(events);
+        self
+    }
+
+    /// The number of events in the payload
+    #[must_use]
+    pub fn event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Check every event, collecting all of the problems found rather than stopping at
+    /// the first.  Each event must have a non-empty routing key of the documented form,
+    /// and a `finish_timestamp` no earlier than its `start_timestamp` when both are set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::Validation` with each problem, prefixed with the event index.
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let mut problems = vec![];
+
+        for (idx, event) in self.events.iter().enumerate() {
+            if event.routing_key.is_empty() {
+                problems.push(format!("event {}: empty routing key", idx));
+            } else if !is_valid_routing_key(&event.routing_key) {
+                problems.push(format!(
+                    "event {}: invalid routing key {:?}",
+                    idx, event.routing_key
+                ));
+            }
+
+            if let (Some(start), Some(finish)) = (event.start_timestamp, event.finish_timestamp) {
+                if finish < start {
+                    problems.push(format!(
+                        "event {}: finish timestamp {} precedes start timestamp {}",
+                        idx, finish, start
+                    ));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrKind::Validation(problems).into())
+        }
+    }
+
+    /// The length in bytes of the events serialized as a JSON array, as sent by default,
+    /// without any of the `Spawner` enrichment, field casing, root wrapper, or
+    /// compression.  The bytes are counted rather than buffered, so this is a cheap way
+    /// to check a batch against the `Spawner` `max_payload_bytes` cap.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the events can't be serialized.
+    pub fn serialized_len(&self) -> crate::error::Result<usize> {
+        body::json_len(&self.events)
+    }
+
+    /// Serialize the events one at a time into a JSON array, skipping any event that
+    /// can't be serialized rather than failing the batch.  The skipped events are
+    /// returned with their index and serialization error.  `spawn` serializes JSON
+    /// bodies the same way, dropping the skipped events with a warning.
+    #[must_use]
+    pub fn serialize_events(&self) -> (String, Vec<(usize, crate::error::Err)>) {
+        let (serialized, errors) = FieldCasing::Camel.serialize_each(&self.events);
+        (format!("[{}]", serialized.join(",")), errors)
+    }
+
+    /// The payload logger
+    pub(crate) fn logger(&self) -> Option<&Logger> {
+        self.logger.as_ref()
+    }
+
+    /// Build the exact `hyper` request the given `Spawner` would send for this payload,
+    /// so it can be sent with your own client or middleware.
+    ///
+    /// The request is influenced by the payload `url`, `events`, `headers`, and `format`,
+    /// and by the following `Spawner` configuration:
+    ///
+    /// * `strict_validation` - payloads failing `validate` fail the build
+    /// * `default_url` - overrides the payload url
+    /// * `min_severity` - events below the minimum severity are dropped
+    /// * `sampling` - events losing the sampling roll are dropped
+    /// * `empty_routing_key` - events with an empty routing key fail the build or are dropped
+    /// * `dedup_batches` - removes duplicate events from the batch
+    /// * `direction_check` - events failing the check can fail the build
+    /// * `response_field_check` - events failing the check can fail the build
+    /// * `max_detail_keys` - caps the `message_detail` keys of each event
+    /// * `auto_correlate` - sets one generated correlation id on every event without one
+    /// * `data_center` - set on any event without a data center
+    /// * `default_host` - set on any event without a host
+    /// * `tenant_id` - stamped on every event
+    /// * `auto_timestamp` - sets the `timestamp` of any event without one
+    /// * `protobuf` - encodes the events as protobuf rather than JSON (`protobuf` feature)
+    /// * `field_casing` - the casing of the serialized JSON field names
+    /// * `root_wrapper` - wraps the JSON events array in a root object
+    /// * `single_event_path` - sends a single JSON event as a bare object to this path
+    /// * `max_payload_bytes` - payloads serializing over the cap fail the build
+    /// * `compression` - gzips the body when that makes it smaller
+    /// * `sequence_events` - stamps a `sequence` number on each event
+    /// * `token_file` - adds an `Authorization` bearer token header, overriding any
+    ///   payload `Authorization` header
+    /// * `bearer_token` - adds an `Authorization` bearer token header when there is no
+    ///   `token_file`, overriding any payload `Authorization` header
+    ///
+    /// # Errors
+    ///
+    pub fn to_request(&self, spawner: &Spawner) -> crate::error::Result<Request<Body>> {
+        let events = self.prepare_events(spawner)?;
+        self.build_request(spawner, &events)?.0.request()
+    }
+
+    /// A copy of this payload holding only the events rejected by the collector, for
+    /// resending them without duplicating the accepted events.  The rejection must come
+    /// from sending this payload, so its indices are those of the payload events.
+    #[must_use]
+    pub fn rejected_subset(&self, rejection: &BatchRejection) -> Self {
+        let mut payload = self.clone();
+        payload.events = rejection.select(&self.events);
+        payload.retry_count += 1;
+        payload
+    }
+
+    /// Apply the `Spawner` filtering, validation, and enrichment to a copy of the events
+    fn prepare_events(&self, spawner: &Spawner) -> crate::error::Result<Vec<Event>> {
+        self.prepare_correlated(spawner)
+            .map(|(events, _, _)| events)
+    }
+
+    /// Prepare the events, also returning any correlation id assigned by `auto_correlate`
+    fn prepare_correlated(
+        &self,
+        spawner: &Spawner,
+    ) -> crate::error::Result<(Vec<Event>, Vec<usize>, Option<Uuid>)> {
+        if spawner.strict_validation {
+            self.validate()?;
+        }
+
+        if spawner.reject_system_events && !spawner.system_sender {
+            if let Some(idx) = self
+                .events
+                .iter()
+                .position(|e| e.event_type == EventType::System)
+            {
+                return Err(ErrKind::ReservedEventType(idx).into());
+            }
+        }
+        let logger = spawner.logger_for(self);
+        let mut events = self.events.clone();
+        let mut origins: Vec<usize> = (0..events.len()).collect();
+
+        if let Some(min_severity) = &spawner.min_severity {
+            let before = events.len();
+            retain_indexed(&mut events, &mut origins, |event| {
+                event.event_type.severity() >= min_severity.severity()
+            });
+            Stats::add(&spawner.stats.filtered, before - events.len());
+        }
+
+        if let Some(sampling) = &spawner.sampling {
+            let before = events.len();
+            retain_indexed(&mut events, &mut origins, |event| {
+                sampling.keep(&event.event_type)
+            });
+
+            if events.len() < before {
+                Stats::add(&spawner.stats.sampled, before - events.len());
+                try_debug!(
+                    logger,
+                    "Sampling dropped {} of {} events",
+                    before - events.len(),
+                    before
+                );
+            }
+        }
+
+        match spawner.empty_routing_key {
+            InvalidEventPolicy::Fail => {
+                if let Some(idx) = events.iter().position(|e| e.routing_key.is_empty()) {
+                    return Err(ErrKind::EmptyRoutingKey(origins[idx]).into());
+                }
+            }
+            InvalidEventPolicy::Drop => {
+                let mut payload_idx = origins.clone().into_iter();
+                retain_indexed(&mut events, &mut origins, |event| {
+                    let idx = payload_idx.next().unwrap_or_default();
+                    let keep = !event.routing_key.is_empty();
+                    if !keep {
+                        try_warn!(logger, "Dropping event {} with empty routing key", idx);
+                    }
+                    keep
+                });
+            }
+        }
+
+        if spawner.dedup_batches {
+            let removed = remove_duplicates(&mut events, &mut origins, Event::fingerprint);
+
+            if removed > 0 {
+                try_debug!(
+                    logger,
+                    "Removed {} duplicate events from the batch",
+                    removed
+                );
+            }
+        }
+
+        // Problems are logged with the payload index of the event
+        for (event, idx) in events.iter().zip(&origins) {
+            spawner
+                .direction_check
+                .enforce(&logger, *idx, || event.check_direction())?;
+            spawner
+                .response_field_check
+                .enforce(&logger, *idx, || event.check_response_fields())?;
+        }
+
+        if let Some(max) = spawner.max_detail_keys {
+            for (event, idx) in events.iter_mut().zip(&origins) {
+                let dropped = event.truncate_message_detail(max);
+                if dropped > 0 {
+                    try_warn!(
+                        logger,
+                        "Truncated {} message detail keys on event {}",
+                        dropped,
+                        idx
+                    );
+                }
+            }
+        }
+
+        if let Some(template) = &self.message_template {
+            for event in events.iter_mut().filter(|e| e.message.is_empty()) {
+                event.message = event.render_template(template);
+            }
+        }
+
+        let mut correlation_id = None;
+
+        if spawner.auto_correlate {
+            for event in events.iter_mut().filter(|e| e.correlation_id.is_none()) {
+                event.correlation_id = Some(*correlation_id.get_or_insert_with(Uuid::new_v4));
+            }
+        }
+
+        if let Some(data_center) = &spawner.data_center {
+            for event in events.iter_mut().filter(|e| e.data_center.is_none()) {
+                event.data_center = Some(data_center.clone());
+            }
+        }
+
+        if let Some(host) = &spawner.default_host {
+            for event in events.iter_mut().filter(|e| e.host.is_none()) {
+                event.host = Some(host.clone());
+            }
+        }
+
+        if let Some(tenant_id) = &spawner.tenant_id {
+            for event in &mut events {
+                event.tenant_id = Some(tenant_id.clone());
+            }
+        }
+
+        if spawner.auto_timestamp {
+            let now = Utc::now().timestamp_millis();
+
+            for event in events.iter_mut().filter(|e| e.timestamp.is_none()) {
+                event.timestamp = Some(Timestamp::Millis(now));
+            }
+        }
+
+        if spawner.sequence_events {
+            for event in events.iter_mut().filter(|e| e.sequence.is_none()) {
+                event.sequence = Some(spawner.sequence.fetch_add(1, Ordering::Relaxed));
+            }
+        }
+
+        Ok((events, origins, correlation_id))
+    }
+
+    /// Serialize the prepared events and build the collector request
+    fn build_request(
+        &self,
+        spawner: &Spawner,
+        events: &[Event],
+    ) -> crate::error::Result<(CollectorRequest, BodySize)> {
+        let (uri, encoder) = self.body_encoder(spawner, events)?;
+        encoder.request(&self.request_template(spawner, &uri)?, events)
+    }
+
+    /// The collector request to `uri`, with the payload and `Authorization` headers, but
+    /// without a body
+    fn request_template(
+        &self,
+        spawner: &Spawner,
+        uri: &str,
+    ) -> crate::error::Result<CollectorRequest> {
+        let mut headers = HeaderMap::new();
+        let logger = spawner.logger_for(self);
+
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(hyper::http::Error::from)?;
+
+            if RESERVED_HEADERS.contains(&name) {
+                try_warn!(logger, "Ignoring reserved payload header {}", name);
+                continue;
+            }
+            let value = HeaderValue::from_str(value).map_err(hyper::http::Error::from)?;
+            let _ = headers.insert(name, value);
+        }
+
+        if let Some(authorization) = spawner.authorization()? {
+            let _ = headers.insert(AUTHORIZATION, authorization);
+        }
+
+        let body = PreparedBody {
+            bytes: vec![],
+            encoding: None,
+        };
+        CollectorRequest::new(uri, "application/json", body, headers, spawner)
+    }
+
+    /// The collector uri for the prepared events, and the encoder of their body
+    fn body_encoder(
+        &self,
+        spawner: &Spawner,
+        events: &[Event],
+    ) -> crate::error::Result<(String, BodyEncoder)> {
+        let config = spawner.body_config(self.format);
+        let uri = spawner
+            .default_url()
+            .unwrap_or_else(|| self.url.clone())
+            .as_str()
+            .into_owned();
+        let encoder = |single_event| BodyEncoder {
+            config: config.clone(),
+            single_event,
+            max_payload_bytes: spawner.max_payload_bytes,
+            logger: spawner.logger_for(self),
+        };
+
+        match (&spawner.single_event_path, events) {
+            (Some(path), [_]) if config.is_json() && self.format == PayloadFormat::JsonArray => {
+                Ok((with_path(&uri, path)?, encoder(true)))
+            }
+            _ => Ok((uri, encoder(false))),
+        }
+    }
+
+    /// Serialize the prepared events, returning the collector uri, the body before
+    /// compression, and its `Content-Type`
+    fn encode(
+        &self,
+        spawner: &Spawner,
+        events: &[Event],
+    ) -> crate::error::Result<(String, Vec<u8>, &'static str)> {
+        let (uri, encoder) = self.body_encoder(spawner, events)?;
+        let (content, content_type) = encoder.encode(events)?;
+        Ok((uri, content, content_type))
+    }
+}
+
+/// Correlate an ordered array of per-event collector acks with the correlation ids of
+/// the prepared events that were sent.  Acks are aligned with events by index.  Events
+/// without a correlation id are skipped, and if the number of acks doesn't match the
+/// number of events, only the overlapping prefix is correlated.
+fn correlate_acks(
+    events: &[Event],
+    acks: Vec<CollectorAck>,
+    logger: &Option<Logger>,
+) -> Vec<(Uuid, CollectorAck)> {
+    if acks.len() != events.len() {
+        try_warn!(
+            logger,
+            "Received {} acks for {} events",
+            acks.len(),
+            events.len()
+        );
+    }
+
+    events
+        .iter()
+        .zip(acks)
+        .filter_map(|(event, ack)| event.correlation_id.map(|id| (id, ack)))
+        .collect()
+}
+
+/// An Echo Event
+///
+/// Use an `EventBuilder` to construct an event with the required fields set.
+#[derive(Clone, Debug, Default, Deserialize, Getters, PartialEq, Serialize, Setters)]
+pub struct Event {
+    /// The routing_key is what identifies the message with an application. It will become the ElasticSearch index.
+    /// Valid characters are lowercase alpha numeric and '-'.
+    /// The key should follow the format <application group>-<application name>-<environment>.
+    #[get = "pub"]
+    #[serde(rename = "routingKey")]
+    routing_key: String,
+    /// Echo Event Type
+    #[get = "pub"]
+    #[serde(rename = "type")]
+    #[set = "pub"]
+    event_type: EventType,
+    /// A simple string message.  Most messages should be one line of information.  If you have secondary, deeper information to store, put it in the `message_detail`.
+    ///
+    /// This field holds the data when the tail appender or default log appender is used.
+    #[get = "pub"]
+    message: String,
+    /// The correlation id
+    #[get = "pub"]
+    #[set = "pub"]
+    #[serde(rename = "correlationId", skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<Uuid>,
+    /// The timestamp of the event.  If unset, it will be set by the EchoClient.
+    ///
+    /// If producing your own messages, the format of the date should be either of:
+    ///
+    /// * An ISO-8601 date/time string (e.g. 2017-04-06T17:23:00-04:00)
+    /// * A number representing milliseconds since epoch (e.g. 1491514054000)
+    ///
+    /// The timestamp is serialized as milliseconds, unless set with
+    /// `set_timestamp_iso8601`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<Timestamp>,
+    /// A place to store custom key/value pairs in the message, typically used when there isn't an appropriate root-level field.
+    #[get = "pub"]
+    #[set = "pub"]
+    #[serde(rename = "messageDetail", skip_serializing_if = "Option::is_none")]
+    message_detail: Option<HashMap<String, String>>,
+    /// Hostname where the message originated. If None, it will be set by the EchoClient.
+    #[get = "pub"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    host: Option<String>,
+    /// Sets the version of the application that is creating this message.
+    #[get = "pub"]
+    #[serde(rename = "applicationVersion", skip_serializing_if = "Option::is_none")]
+    application_version: Option<String>,
+    /// Sets the datacenter that the application is in, based on DCPloy environment settings.
+    #[get = "pub"]
+    #[serde(rename = "dataCenter", skip_serializing_if = "Option::is_none")]
+    data_center: Option<String>,
+    /// The hostname of a client if this message is involving an external system calling into your system.
+    #[get = "pub"]
+    #[serde(rename = "clientHostName", skip_serializing_if = "Option::is_none")]
+    client_host_name: Option<String>,
+    /// The hostname of a destination system if this message is involving your system calling an external system.
+    #[get = "pub"]
+    #[serde(
+        rename = "destinationHostName",
+        skip_serializing_if = "Option::is_none"
+    )]
+    destination_host_name: Option<String>,
+    /// The path being called on a destination system if this message is involving your system calling an external system.
+    #[get = "pub"]
+    #[serde(rename = "destinationPath", skip_serializing_if = "Option::is_none")]
+    destination_path: Option<String>,
+    /// Sets the timestamp of millis since the epoch for the time at which this event started.
+    #[get = "pub"]
+    #[serde(rename = "startTimestamp", skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    start_timestamp: Option<u64>,
+    /// Sets the timestamp of millis since the epoch for the time at which this event finished.
+    #[get = "pub"]
+    #[serde(rename = "finishTimestamp", skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    finish_timestamp: Option<u64>,
+    /// Sets the duration (time in milliseconds) that passed during this event.
+    #[get = "pub"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    duration: Option<u64>,
+    /// Sets the duration (time in milliseconds) that passed during this event.
+    #[get = "pub"]
+    #[serde(rename = "durationInMs", skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    duration_in_ms: Option<u64>,
+    /// The HTTP response code returned by a performance event.
+    #[get = "pub"]
+    #[serde(rename = "responseCode", skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    response_code: Option<u16>,
+    /// A more generic response used when a HTTP response code doesn't make sense. Typical values might be "success" or "failure".
+    #[get = "pub"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    response: Option<Response>,
+    /// A monotonic tiebreaker for events sharing a timestamp, used by collectors that
+    /// support restoring submission order.
+    #[get = "pub"]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[set = "pub"]
+    sequence: Option<u64>,
+    /// The id of the tenant, or account, the event belongs to on a multi-tenant platform.
+    #[get = "pub"]
+    #[serde(rename = "tenantId", skip_serializing_if = "Option::is_none")]
+    tenant_id: Option<String>,
+}
+
+impl Event {
+    /// Set the routing key field
+    pub fn set_routing_key<T>(&mut self, routing_key: T) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.routing_key = routing_key.into();
+        self
+    }
+
+    /// Set the routing key field, validating that it only contains lowercase alphanumeric
+    /// characters and `-`, and has the `<application group>-<application name>-<environment>`
+    /// form.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::InvalidRoutingKey` if the key is invalid, leaving the routing key
+    /// unchanged.
+    pub fn try_set_routing_key<T>(&mut self, routing_key: T) -> crate::error::Result<&mut Self>
+    where
+        T: Into<String>,
+    {
+        let routing_key = routing_key.into();
+
+        if is_valid_routing_key(&routing_key) {
+            self.routing_key = routing_key;
+            Ok(self)
+        } else {
+            Err(ErrKind::InvalidRoutingKey(routing_key).into())
+        }
+    }
+
+    /// Set the message field
+    pub fn set_message<T>(&mut self, message: T) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.message = message.into();
+        self
+    }
+
+    /// Set the host field
+    pub fn set_host<T>(&mut self, host: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.host = match host {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the application version field
+    pub fn set_application_version<T>(&mut self, application_version: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.application_version = match application_version {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the datacenter field
+    pub fn set_data_center<T>(&mut self, data_center: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.data_center = match data_center {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the tenant id field
+    pub fn set_tenant_id<T>(&mut self, tenant_id: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.tenant_id = match tenant_id {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the client host name
+    pub fn set_client_host_name<T>(&mut self, client_host_name: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.client_host_name = match client_host_name {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the destination host name
+    pub fn set_destination_host_name<T>(&mut self, destination_host_name: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.destination_host_name = match destination_host_name {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// Set the destination path
+    pub fn set_destination_path<T>(&mut self, destination_path: Option<T>) -> &mut Self
+    where
+        T: Into<String>,
+    {
+        self.destination_path = match destination_path {
+            None => None,
+            Some(t) => Some(t.into()),
+        };
+        self
+    }
+
+    /// The timestamp field, in milliseconds since the epoch
+    #[must_use]
+    pub fn timestamp(&self) -> Option<i64> {
+        self.timestamp.map(Timestamp::millis)
+    }
+
+    /// Set the timestamp field, in milliseconds since the epoch
+    pub fn set_timestamp(&mut self, timestamp: Option<i64>) -> &mut Self {
+        self.timestamp = timestamp.map(Timestamp::Millis);
+        self
+    }
+
+    /// Set the timestamp field from a `DateTime`
+    pub fn set_timestamp_dt(&mut self, dt: DateTime<Utc>) -> &mut Self {
+        self.set_timestamp(Some(dt.timestamp_millis()))
+    }
+
+    /// Set the timestamp field from a `DateTime`, serializing it as an ISO-8601 (RFC 3339)
+    /// string with millisecond precision, i.e. `2017-04-06T21:23:00.000Z`, for
+    /// collectors expecting the string form
+    pub fn set_timestamp_iso8601(&mut self, dt: DateTime<Utc>) -> &mut Self {
+        self.timestamp = Some(Timestamp::Rfc3339(dt.timestamp_millis()));
+        self
+    }
+
+    /// Set the start timestamp field from a `DateTime`.  Times before the epoch clear
+    /// the field.
+    pub fn set_start_timestamp_dt(&mut self, dt: DateTime<Utc>) -> &mut Self {
+        self.start_timestamp = u64::try_from(dt.timestamp_millis()).ok();
+        self
+    }
+
+    /// Set the finish timestamp field from a `DateTime`.  Times before the epoch clear
+    /// the field.
+    pub fn set_finish_timestamp_dt(&mut self, dt: DateTime<Utc>) -> &mut Self {
+        self.finish_timestamp = u64::try_from(dt.timestamp_millis()).ok();
+        self
+    }
+
+    /// Set the correlation id from its string form
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::ParseUuid` if the string isn't a valid uuid, leaving the event
+    /// unchanged.
+    pub fn set_correlation_id_str<T>(&mut self, id: T) -> crate::error::Result<&mut Self>
+    where
+        T: AsRef<str>,
+    {
+        self.correlation_id = Some(Uuid::parse_str(id.as_ref())?);
+        Ok(self)
+    }
+
+    /// Set a newly generated (v4) correlation id
+    pub fn new_correlation_id(&mut self) -> &mut Self {
+        self.correlation_id = Some(Uuid::new_v4());
+        self
+    }
+
+    /// A copy of this event with the message replaced, for emitting many events from a
+    /// template event, i.e. `templates.iter().map(|t| t.with_message("done"))`
+    #[must_use]
+    pub fn with_message<T>(&self, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        let mut event = self.clone();
+        event.message = message.into();
+        event
+    }
+
+    /// A copy of this event with the event type replaced
+    #[must_use]
+    pub fn with_event_type(&self, event_type: EventType) -> Self {
+        let mut event = self.clone();
+        event.event_type = event_type;
+        event
+    }
+
+    /// Add a `message_detail` entry, replacing any existing value for the key
+    pub fn add_message_detail<K, V>(&mut self, key: K, value: V) -> &mut Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let _ = self
+            .message_detail
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Remove a `message_detail` entry.  Removing the last entry clears the field.
+    pub fn remove_message_detail(&mut self, key: &str) -> &mut Self {
+        if let Some(detail) = &mut self.message_detail {
+            let _ = detail.remove(key);
+
+            if detail.is_empty() {
+                self.message_detail = None;
+            }
+        }
+        self
+    }
+
+    /// Mark the event started now, setting the start timestamp field
+    pub fn mark_start(&mut self) -> &mut Self {
+        self.set_start_timestamp_dt(Utc::now())
+    }
+
+    /// Mark the event finished now, setting the finish timestamp field and deriving both
+    /// the `duration` and `duration_in_ms` fields from the start timestamp, so the
+    /// timing fields of performance events stay consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::FinishBeforeStart`, leaving the event unchanged, if the event
+    /// hasn't been marked started or was started after now.
+    pub fn mark_finish(&mut self) -> crate::error::Result<&mut Self> {
+        self.finish_at(Utc::now())
+    }
+
+    /// Mark the event finished at `dt`
+    fn finish_at(&mut self, dt: DateTime<Utc>) -> crate::error::Result<&mut Self> {
+        let finish = u64::try_from(dt.timestamp_millis()).unwrap_or(0);
+        let start = self.start_timestamp;
+        let elapsed = start
+            .and_then(|start| finish.checked_sub(start))
+            .ok_or(ErrKind::FinishBeforeStart { start, finish })?;
+
+        self.finish_timestamp = Some(finish);
+        self.duration = Some(elapsed);
+        self.duration_in_ms = Some(elapsed);
+        Ok(self)
+    }
+
+    /// A fingerprint of every field of the event.  Identical events have identical
+    /// fingerprints, regardless of the iteration order of their `message_detail`.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.routing_key.hash(&mut hasher);
+        self.event_type.hash(&mut hasher);
+        self.message.hash(&mut hasher);
+        self.correlation_id.hash(&mut hasher);
+        self.timestamp.hash(&mut hasher);
+        self.message_detail
+            .as_ref()
+            .map(|detail| {
+                let mut entries: Vec<_> = detail.iter().collect();
+                entries.sort();
+                entries
+            })
+            .hash(&mut hasher);
+        self.host.hash(&mut hasher);
+        self.application_version.hash(&mut hasher);
+        self.data_center.hash(&mut hasher);
+        self.client_host_name.hash(&mut hasher);
+        self.destination_host_name.hash(&mut hasher);
+        self.destination_path.hash(&mut hasher);
+        self.start_timestamp.hash(&mut hasher);
+        self.finish_timestamp.hash(&mut hasher);
+        self.duration.hash(&mut hasher);
+        self.duration_in_ms.hash(&mut hasher);
+        self.response_code.hash(&mut hasher);
+        self.response.hash(&mut hasher);
+        self.sequence.hash(&mut hasher);
+        self.tenant_id.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The length in bytes of the event serialized as JSON, as sent by default, without
+    /// any of the `Spawner` enrichment.  The bytes are counted rather than buffered.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the event can't be serialized.
+    pub fn serialized_len(&self) -> crate::error::Result<usize> {
+        body::json_len(self)
+    }
+
+    /// Compare the identifying fields of two events, `routing_key`, `event_type`,
+    /// `message`, `correlation_id`, and `message_detail`, ignoring the volatile timing
+    /// and host fields, i.e. to drop duplicate events emitted by retrying callers.
+    #[must_use]
+    pub fn semantic_eq(&self, other: &Self) -> bool {
+        self.routing_key == other.routing_key
+            && self.event_type == other.event_type
+            && self.message == other.message
+            && self.correlation_id == other.correlation_id
+            && self.message_detail == other.message_detail
+    }
+
+    /// Check that this event doesn't describe both an inbound call (`client_host_name`
+    /// set) and an outbound call (`destination_host_name` or `destination_path` set).
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::AmbiguousDirection` if both directions are present.
+    pub fn check_direction(&self) -> crate::error::Result<()> {
+        let outbound = self.destination_host_name.is_some() || self.destination_path.is_some();
+
+        if self.client_host_name.is_some() && outbound {
+            Err(ErrKind::AmbiguousDirection.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that the `response_code` and `response` fields are only set on event types
+    /// they make sense for.  Both fields describe the result of a timed operation, so
+    /// they are only valid on PERFORMANCE events.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ErrKind::MisplacedResponseField` if either field is set on any other
+    /// event type.
+    pub fn check_response_fields(&self) -> crate::error::Result<()> {
+        let has_response = self.response_code.is_some() || self.response.is_some();
+
+        if has_response && self.event_type != EventType::Performance {
+            Err(ErrKind::MisplacedResponseField(self.event_type.clone()).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Render the template, replacing each `{key}` with the matching `message_detail`
+    /// value, or an empty string if there is no such key.
+    pub(crate) fn render_template(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+
+            match rest[start..].find('}') {
+                Some(len) => {
+                    let key = &rest[start + 1..start + len];
+                    if let Some(value) = self.message_detail.as_ref().and_then(|d| d.get(key)) {
+                        rendered.push_str(value);
+                    }
+                    rest = &rest[start + len + 1..];
+                }
+                None => {
+                    rendered.push_str(&rest[start..]);
+                    rest = "";
+                }
+            }
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// Cap the `message_detail` map at `max` keys.  Keys are kept in sorted order for
+    /// determinism, and the last slot is used for a `libechoexec.truncated_keys` entry
+    /// listing the keys that were dropped.  A cap of 0 drops every key, without the
+    /// entry.  Returns the number of keys dropped.
+    pub(crate) fn truncate_message_detail(&mut self, max: usize) -> usize {
+        let detail = match &mut self.message_detail {
+            Some(detail) if detail.len() > max => detail,
+            _ => return 0,
+        };
+
+        if max == 0 {
+            let dropped = detail.len();
+            self.message_detail = None;
+            return dropped;
+        }
+
+        let mut keys: Vec<String> = detail.keys().cloned().collect();
+        keys.sort();
+        let dropped = keys.split_off(max.saturating_sub(1));
+
+        for key in &dropped {
+            let _ = detail.remove(key);
+        }
+        let _ = detail.insert(TRUNCATED_KEYS.to_string(), dropped.join(","));
+        dropped.len()
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl From<&Event> for ProtoEvent {
+    fn from(event: &Event) -> Self {
+        Self {
+            routing_key: event.routing_key.clone(),
+            event_type: event.event_type.as_str().to_string(),
+            message: event.message.clone(),
+            correlation_id: event.correlation_id.map(|id| id.to_string()),
+            timestamp: event.timestamp(),
+            message_detail: event.message_detail.clone().unwrap_or_default(),
+            host: event.host.clone(),
+            application_version: event.application_version.clone(),
+            data_center: event.data_center.clone(),
+            client_host_name: event.client_host_name.clone(),
+            destination_host_name: event.destination_host_name.clone(),
+            destination_path: event.destination_path.clone(),
+            start_timestamp: event.start_timestamp,
+            finish_timestamp: event.finish_timestamp,
+            duration: event.duration,
+            duration_in_ms: event.duration_in_ms,
+            response_code: event.response_code.map(u32::from),
+            response: event.response.map(|r| r.as_str().to_string()),
+            sequence: event.sequence,
+            tenant_id: event.tenant_id.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl TryFrom<ProtoEvent> for Event {
+    type Error = crate::error::Err;
+
+    fn try_from(proto: ProtoEvent) -> crate::error::Result<Self> {
+        Ok(Self {
+            routing_key: proto.routing_key,
+            event_type: EventTypeVisitor
+                .visit_str::<serde::de::value::Error>(&proto.event_type)
+                .map_err(|e| e.to_string())?,
+            message: proto.message,
+            correlation_id: match proto.correlation_id {
+                Some(id) => Some(Uuid::parse_str(&id)?),
+                None => None,
+            },
+            timestamp: proto.timestamp.map(Timestamp::Millis),
+            message_detail: if proto.message_detail.is_empty() {
+                None
+            } else {
+                Some(proto.message_detail)
+            },
+            host: proto.host,
+            application_version: proto.application_version,
+            data_center: proto.data_center,
+            client_host_name: proto.client_host_name,
+            destination_host_name: proto.destination_host_name,
+            destination_path: proto.destination_path,
+            start_timestamp: proto.start_timestamp,
+            finish_timestamp: proto.finish_timestamp,
+            duration: proto.duration,
+            duration_in_ms: proto.duration_in_ms,
+            response_code: match proto.response_code {
+                Some(code) => Some(u16::try_from(code).map_err(|e| e.to_string())?),
+                None => None,
+            },
+            response: match proto.response {
+                Some(response) => Some(
+                    ResponseVisitor
+                        .visit_str::<serde::de::value::Error>(&response)
+                        .map_err(|e| e.to_string())?,
+                ),
+                None => None,
+            },
+            sequence: proto.sequence,
+            tenant_id: proto.tenant_id,
+        })
+    }
+}
+
+/// Echo Event Type
+///
+/// The following types are currently recognized:
+///
+/// * ERROR - Any message that should be associated with a non-normal action or situation that the system processed.
+/// * INFO - Any message that should be associated with a normal action or situation that the system processed.
+/// * PERFORMANCE - Any message that associates speed or time taken with which any action or situation that the system processed.
+/// * TRACKING - Any message that tries to correlate two (or more) events or data points that is not associated.
+/// * SYSTEM - Internally used for client machine performance data (CPU utilization, JVM heap usage, ect)
+///
+/// Additional types may be added in the future.  Until they are, use `EventType::custom`
+/// to send any type the collector accepts.
+///
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum EventType {
+    /// ERROR
+    Error,
+    /// INFO
+    Info,
+    /// PERFORMANCE
+    Performance,
+    /// TRACKING
+    Tracking,
+    /// SYSTEM
+    System,
+    /// Any other type the collector accepts, i.e. AUDIT.  Create it with
+    /// `EventType::custom`, so the name is uppercased once and metrics, sampling and
+    /// the wire all see the same name.  Parsing and deserializing only accept the
+    /// recognized types, so a typo isn't taken for a custom type, and events with a
+    /// custom type can't be deserialized.
+    Custom(CustomEventType),
+}
+
+/// The uppercased name of a `Custom` event type, only created by `EventType::custom`
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct CustomEventType(String);
+
+impl CustomEventType {
+    /// The event type name
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for EventType {
+    fn default() -> Self {
+        Self::Info
+    }
+}
+
+impl EventType {
+    /// Create an event type from any name the collector accepts, uppercased.  The
+    /// recognized names map to their own variants, i.e. `custom("error")` is `Error`.
+    #[must_use]
+    pub fn custom<T: Into<String>>(name: T) -> Self {
+        let name = name.into().to_uppercase();
+
+        match name.as_str() {
+            "ERROR" => Self::Error,
+            "INFO" => Self::Info,
+            "PERFORMANCE" => Self::Performance,
+            "TRACKING" => Self::Tracking,
+            "SYSTEM" => Self::System,
+            _ => Self::Custom(CustomEventType(name)),
+        }
+    }
+
+    /// Convert the enum to a str
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Info => "INFO",
+            Self::Performance => "PERFORMANCE",
+            Self::Tracking => "TRACKING",
+            Self::System => "SYSTEM",
+            Self::Custom(event_type) => event_type.as_str(),
+        }
+    }
+
+    /// The severity of the event type, used by the `Spawner` `min_severity` filter.
+    ///
+    /// From least to most severe: SYSTEM, TRACKING, PERFORMANCE, INFO, ERROR.  `Custom`
+    /// types are as severe as INFO.
+    #[must_use]
+    pub fn severity(&self) -> u8 {
+        match self {
+            Self::System => 0,
+            Self::Tracking => 1,
+            Self::Performance => 2,
+            Self::Info | Self::Custom(_) => 3,
+            Self::Error => 4,
+        }
+    }
+}
+
+impl FromStr for EventType {
+    type Err = crate::error::Err;
+
+    /// Parse one of the recognized event types, ignoring case.  Any other name is an
+    /// error, use `EventType::custom` for other types the collector accepts.
+    fn from_str(value: &str) -> crate::error::Result<Self> {
+        match Self::custom(value) {
+            Self::Custom(_) => Err(ErrKind::ParseEventType(value.to_string()).into()),
+            event_type => Ok(event_type),
+        }
+    }
+}
+
+impl TryFrom<&str> for EventType {
+    type Error = crate::error::Err;
+
+    fn try_from(value: &str) -> crate::error::Result<Self> {
+        value.parse()
+    }
+}
+
+impl Ser for EventType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Custom(event_type) => serializer.serialize_str(&event_type.0.to_uppercase()),
+            _ => serializer.serialize_str(self.as_str()),
+        }
+    }
+}
+
+impl<'de> Deser<'de> for EventType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(EventTypeVisitor)
+    }
+}
+
+struct EventTypeVisitor;
+
+impl Visitor<'_> for EventTypeVisitor {
+    type Value = EventType;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an event type, i.e. ERROR or INFO")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        value
+            .parse()
+            .map_err(|_| E::custom(format!("invalid event type: {}", value)))
+    }
+}
+
+/// A more generic response used when a HTTP response code doesn't make sense. Typical values might be "success" or "failure".
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Response {
+    /// Success
+    Success,
+    /// Failure
+    Failure,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self::Success
+    }
+}
+
+impl FromStr for Response {
+    type Err = crate::error::Err;
+
+    /// Parse a response, ignoring case
+    fn from_str(value: &str) -> crate::error::Result<Self> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "success" => Self::Success,
+            "failure" => Self::Failure,
+            _ => return Err(ErrKind::ParseResponse(value.to_string()).into()),
+        })
+    }
+}
+
+impl TryFrom<&str> for Response {
+    type Error = crate::error::Err;
+
+    fn try_from(value: &str) -> crate::error::Result<Self> {
+        value.parse()
+    }
+}
+
+impl Response {
+    /// Convert the enum to a str
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+impl Ser for Response {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deser<'de> for Response {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(ResponseVisitor)
+    }
+}
+
+struct ResponseVisitor;
+
+impl Visitor<'_> for ResponseVisitor {
+    type Value = Response;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("one of success or failure")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(match value {
+            "success" => Response::Success,
+            "failure" => Response::Failure,
+            _ => return Err(E::custom(format!("invalid response: {}", value))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{
+            group_by_url, is_transient, remove_duplicates, retry_delay, BatchAck, BatchRejection,
+            CollectorAck, CollectorUrl, Event, EventType, Payload, PersistablePayload, Response,
+            Spawner, MAX_ACK_BODY_BYTES,
+        },
+        crate::{
+            circuit::CircuitState,
+            config::SpawnerConfig,
+            error::{ErrKind, Result},
+            format::PayloadFormat,
+            limit::RateLimitPolicy,
+            metadata::DataCenterResolver,
+            metrics::Metrics,
+            pause::PausePolicy,
+            sampling::SamplingPolicy,
+            stats::ShutdownSummary,
+            validate::InvalidEventPolicy,
+        },
+        chrono::{offset::TimeZone, Utc},
+        futures::{executor::block_on, SinkExt},
+        hyper::{
+            service::{make_service_fn, service_fn},
+            Body, Request, Server, StatusCode,
+        },
+        slog::{o, Drain, Logger},
+        std::{
+            collections::HashMap,
+            convert::{Infallible, TryFrom},
+            env::VarError,
+            net::SocketAddr,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                mpsc::channel,
+                Arc, Mutex,
+            },
+            thread,
+            time::Duration,
+        },
+        tokio::runtime::Handle,
+        uuid::Uuid,
+    };
+
+    /// Run a local collector on the handle, optionally speaking only HTTP/2, responding
+    /// with the status and body returned by `respond` for the request path and count of
+    /// earlier requests, and returning its address and the count of requests.  `429`
+    /// responses ask to be retried immediately, with `Retry-After: 0`.
+    fn local_collector<F>(
+        handle: &Handle,
+        http2_only: bool,
+        respond: F,
+    ) -> (SocketAddr, Arc<AtomicUsize>)
+    where
+        F: Fn(&str, usize) -> (StatusCode, String) + Clone + Send + Sync + 'static,
+    {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let counter = counter.clone();
+            let respond = respond.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let count = counter.fetch_add(1, Ordering::SeqCst);
+                    let (status, body) = respond(req.uri().path(), count);
+                    async move {
+                        let mut builder = hyper::Response::builder().status(status);
+
+                        if status == StatusCode::TOO_MANY_REQUESTS {
+                            builder = builder.header("Retry-After", "0");
+                        }
+                        builder.body(Body::from(body))
+                    }
+                }))
+            }
+        });
+        let server = handle.enter(|| {
+            Server::bind(&([127, 0, 0, 1], 0).into())
+                .http2_only(http2_only)
+                .serve(make_service)
+        });
+        let addr = server.local_addr();
+        let _ = handle.spawn(server);
+        (addr, requests)
+    }
+
+    /// Run a local collector on the handle that responds 503 to the first two requests
+    /// and 200 after, or always 400 to requests for `/bad`
+    fn flaky_collector(handle: &Handle) -> (SocketAddr, Arc<AtomicUsize>) {
+        local_collector(handle, false, |path, count| {
+            let status = if path == "/bad" {
+                StatusCode::BAD_REQUEST
+            } else if count < 2 {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                StatusCode::OK
+            };
+            (status, String::new())
+        })
+    }
+
+    #[test]
+    fn parse_event_type() -> Result<()> {
+        assert_eq!("ERROR".parse::<EventType>()?, EventType::Error);
+        assert_eq!(EventType::try_from("performance")?, EventType::Performance);
+        assert_eq!(EventType::try_from("Tracking")?, EventType::Tracking);
+
+        for invalid in &["audit", "EROR", "DEBUG", " "] {
+            assert!(matches!(
+                EventType::try_from(*invalid)
+                    .as_ref()
+                    .map_err(crate::error::Err::kind),
+                Err(ErrKind::ParseEventType(value)) if value == invalid
+            ));
+        }
+        assert!(serde_json::from_str::<EventType>(r#""EROR""#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_response() -> Result<()> {
+        assert_eq!("success".parse::<Response>()?, Response::Success);
+        assert_eq!(Response::try_from("FAILURE")?, Response::Failure);
+        assert!(Response::try_from("maybe").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_default() -> Result<()> {
+        let echo_event = Event::default();
+        let result = serde_json::to_string(&echo_event)?;
+        assert_eq!(result, r#"{"routingKey":"","type":"INFO","message":""}"#);
+        Ok(())
+    }
+
+    #[test]
+    fn with_message() -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_message("testing");
+        let result = serde_json::to_string(&echo_event)?;
+        assert_eq!(
+            result,
+            r#"{"routingKey":"","type":"INFO","message":"testing"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn with_type() -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_event_type(EventType::Performance);
+        let result = serde_json::to_string(&echo_event)?;
+        assert_eq!(
+            result,
+            r#"{"routingKey":"","type":"PERFORMANCE","message":""}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn full() -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_routing_key("atlas-dev-promises");
+        let _ = echo_event.set_event_type(EventType::System);
+        let _ = echo_event.set_message("testing");
+        let _ = echo_event.set_correlation_id(Some(Uuid::parse_str(
+            "35F3E1D6-D859-4AA0-8C58-2CDFE97A4710",
+        )?));
+        let _ = echo_event.set_timestamp(Some(
+            Utc.ymd(1976, 3, 22)
+                .and_hms_milli(0, 0, 1, 666)
+                .timestamp_millis(),
+        ));
+        let mut message_detail = HashMap::new();
+        let _ = message_detail.insert("a", "b");
+        let _ = echo_event.set_message_detail(Some(
+            message_detail
+                .iter_mut()
+                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                .collect(),
+        ));
+        let _ = echo_event.set_host(Some("host"));
+        let _ = echo_event.set_application_version(Some("1.2.3"));
+        let _ = echo_event.set_data_center(Some("cdc"));
+        let _ = echo_event.set_client_host_name(Some("blah"));
+        let _ = echo_event.set_destination_host_name(Some("blah1"));
+        let _ = echo_event.set_destination_path(Some("yoda"));
+        let _ = echo_event.set_start_timestamp(Some(1));
+        let _ = echo_event.set_finish_timestamp(Some(2));
+        let _ = echo_event.set_duration(Some(3));
+        let _ = echo_event.set_duration_in_ms(Some(4));
+        let _ = echo_event.set_response_code(Some(200));
+        let _ = echo_event.set_response(Some(Response::Failure));
+
+        let result = serde_json::to_string(&echo_event)?;
+        assert_eq!(
+            result,
+            r#"{"routingKey":"atlas-dev-promises","type":"SYSTEM","message":"testing","correlationId":"35f3e1d6-d859-4aa0-8c58-2cdfe97a4710","timestamp":196300801666,"messageDetail":{"a":"b"},"host":"host","applicationVersion":"1.2.3","dataCenter":"cdc","clientHostName":"blah","destinationHostName":"blah1","destinationPath":"yoda","startTimestamp":1,"finishTimestamp":2,"duration":3,"durationInMs":4,"responseCode":200,"response":"failure"}"#
+        );
+        assert_eq!(serde_json::from_str::<Event>(&result)?, echo_event);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_event_type() -> Result<()> {
+        let event_type = EventType::custom("audit");
+        assert_eq!(event_type, EventType::custom("AUDIT"));
+        assert_eq!(event_type.as_str(), "AUDIT");
+        assert_eq!(EventType::custom("error"), EventType::Error);
+        assert_eq!(serde_json::to_string(&event_type)?, r#""AUDIT""#);
+        assert_eq!(EventType::default(), EventType::Info);
+
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .set_routing_key("atlas-dev-promises")
+            .set_event_type(event_type.clone())
+            .set_message("audited");
+        let result = serde_json::to_string(&echo_event)?;
+        assert!(result.contains(r#""type":"AUDIT""#));
+        assert!(serde_json::from_str::<Event>(&result).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_invalid_enums() {
+        assert!(serde_json::from_str::<Event>(
+            r#"{"routingKey":"atlas-dev-promises","type":"","message":""}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<Event>(
+            r#"{"routingKey":"atlas-dev-promises","type":"INFO","message":"","response":"maybe"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn correlation_id_str() -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_correlation_id_str("35F3E1D6-D859-4AA0-8C58-2CDFE97A4710")?;
+        assert_eq!(
+            echo_event.correlation_id,
+            Some(Uuid::parse_str("35f3e1d6-d859-4aa0-8c58-2cdfe97a4710")?)
+        );
+
+        let invalid = echo_event.set_correlation_id_str("not a uuid");
+        assert!(matches!(
+            invalid.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::ParseUuid(_))
+        ));
+        assert!(echo_event.correlation_id.is_some());
+
+        let before = echo_event.correlation_id;
+        let _ = echo_event.new_correlation_id();
+        assert!(echo_event.correlation_id.is_some());
+        assert_ne!(echo_event.correlation_id, before);
+        Ok(())
+    }
+
+    #[test]
+    fn with_message() {
+        let mut template = routed_event(EventType::Info);
+        let _ = template.set_message("template").set_host(Some("host"));
+
+        let events: Vec<Event> = ["a", "b"]
+            .iter()
+            .map(|message| template.with_message(*message))
+            .collect();
+        assert_eq!(events[0].message, "a");
+        assert_eq!(events[1].message, "b");
+        assert_eq!(events[1].host, template.host);
+        assert_eq!(events[1].routing_key, template.routing_key);
+
+        let error = template.with_event_type(EventType::Error);
+        assert_eq!(error.event_type, EventType::Error);
+        assert_eq!(error.message, "template");
+
+        // The template is unchanged
+        assert_eq!(template.message, "template");
+        assert_eq!(template.event_type, EventType::Info);
+    }
+
+    #[test]
+    fn add_message_detail() {
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .add_message_detail("a", "b")
+            .add_message_detail("c".to_string(), "d")
+            .add_message_detail("a", "e");
+        let detail = echo_event.message_detail().as_ref().expect("detail");
+        assert_eq!(detail.len(), 2);
+        assert_eq!(detail.get("a").map(String::as_str), Some("e"));
+        assert_eq!(detail.get("c").map(String::as_str), Some("d"));
+
+        let _ = echo_event
+            .remove_message_detail("a")
+            .remove_message_detail("x");
+        assert_eq!(
+            echo_event.message_detail().as_ref().map(HashMap::len),
+            Some(1)
+        );
+        let _ = echo_event.remove_message_detail("c");
+        assert!(echo_event.message_detail().is_none());
+    }
+
+    #[test]
+    fn truncate_message_detail() {
+        let mut echo_event = Event::default();
+        let mut message_detail = HashMap::new();
+        for key in &["d", "a", "c", "b", "e"] {
+            let _ = message_detail.insert((*key).to_string(), (*key).to_string());
+        }
+        let _ = echo_event.set_message_detail(Some(message_detail));
+
+        assert_eq!(echo_event.truncate_message_detail(5), 0);
+        assert_eq!(echo_event.truncate_message_detail(3), 3);
+
+        let detail = echo_event.message_detail.clone().expect("message detail");
+        assert_eq!(detail.len(), 3);
+        assert_eq!(detail.get("a"), Some(&"a".to_string()));
+        assert_eq!(detail.get("b"), Some(&"b".to_string()));
+        assert_eq!(
+            detail.get("libechoexec.truncated_keys"),
+            Some(&"c,d,e".to_string())
+        );
+
+        // An application key named like the old marker is kept as any other key
+        let mut message_detail = HashMap::new();
+        for key in &["a", "truncated_keys", "z"] {
+            let _ = message_detail.insert((*key).to_string(), (*key).to_string());
+        }
+        let _ = echo_event.set_message_detail(Some(message_detail));
+        assert_eq!(echo_event.truncate_message_detail(3), 0);
+        assert_eq!(echo_event.truncate_message_detail(2), 2);
+        let detail = echo_event.message_detail.clone().expect("message detail");
+        assert_eq!(detail.get("a"), Some(&"a".to_string()));
+        assert_eq!(
+            detail.get("libechoexec.truncated_keys"),
+            Some(&"truncated_keys,z".to_string())
+        );
+
+        assert_eq!(echo_event.truncate_message_detail(0), 2);
+        assert!(echo_event.message_detail.is_none());
+    }
+
+    #[test]
+    fn correlate_acks() -> Result<()> {
+        let first = Uuid::parse_str("35F3E1D6-D859-4AA0-8C58-2CDFE97A4710")?;
+        let second = Uuid::parse_str("8E0C2C2B-7A0D-4C3B-9E53-0A9A2B6F1C11")?;
+        let mut events = vec![];
+
+        for id in &[Some(first), None, Some(second)] {
+            let mut echo_event = Event::default();
+            let _ = echo_event.set_correlation_id(*id);
+            events.push(echo_event);
+        }
+
+        let acks: Vec<CollectorAck> =
+            serde_json::from_str(r#"[{"id":"a"},{"id":"b"},{"id":"c"}]"#)?;
+        let correlated = super::correlate_acks(&events, acks, &None);
+        assert_eq!(correlated.len(), 2);
+        assert_eq!(correlated[0].0, first);
+        assert_eq!(correlated[0].1.id(), "a");
+        assert_eq!(correlated[1].0, second);
+        assert_eq!(correlated[1].1.id(), "c");
+
+        let acks: Vec<CollectorAck> = serde_json::from_str(r#"[{"id":"a"}]"#)?;
+        assert_eq!(super::correlate_acks(&events, acks, &None).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn correlate_acks_after_filtering() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let (addr, _) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, r#"[{"id":"a"},{"id":"b"}]"#.to_string())
+        });
+        let _ = echo_spawner.set_min_severity(Some(EventType::Info));
+        let first = Uuid::parse_str("35F3E1D6-D859-4AA0-8C58-2CDFE97A4710")?;
+        let filtered = Uuid::parse_str("8E0C2C2B-7A0D-4C3B-9E53-0A9A2B6F1C11")?;
+        let mut events = vec![];
+
+        for (event_type, id) in &[
+            (EventType::Info, Some(first)),
+            (EventType::Tracking, Some(filtered)),
+            (EventType::Error, None),
+        ] {
+            let mut echo_event = routed_event(event_type.clone());
+            let _ = echo_event.set_correlation_id(*id);
+            events.push(echo_event);
+        }
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(events.clone());
+
+        // The filtered TRACKING event doesn't shift the acks
+        let ack = block_on(echo_spawner.spawn_with_response(&payload)?)
+            .map_err(|_| "no acknowledgement")?;
+        assert_eq!(*ack.accepted(), 2);
+        assert_eq!(ack.events().len(), 1);
+        assert_eq!(ack.events()[0].0, first);
+        assert_eq!(ack.events()[0].1.id(), "a");
+
+        // Auto-correlated ids are assigned before the acks are correlated
+        let _ = echo_spawner.set_auto_correlate(true);
+        let _ = events[0].set_correlation_id(None);
+        let _ = payload.set_events(events);
+        let ack = block_on(echo_spawner.spawn_with_response(&payload)?)
+            .map_err(|_| "no acknowledgement")?;
+        assert_eq!(ack.events().len(), 2);
+        assert_eq!(ack.events()[0].0, ack.events()[1].0);
+        assert_eq!(ack.events()[1].1.id(), "b");
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_from_datetime() {
+        let start = Utc.ymd(1976, 3, 22).and_hms_milli(0, 0, 1, 666);
+        let finish = Utc.ymd(1976, 3, 22).and_hms_milli(0, 0, 2, 0);
+
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .set_timestamp_dt(start)
+            .set_start_timestamp_dt(start)
+            .set_finish_timestamp_dt(finish);
+        assert_eq!(echo_event.timestamp(), Some(196_300_801_666));
+        assert_eq!(echo_event.start_timestamp, Some(196_300_801_666));
+        assert_eq!(echo_event.finish_timestamp, Some(196_300_802_000));
+
+        let _ = echo_event.set_start_timestamp_dt(Utc.ymd(1969, 1, 1).and_hms(0, 0, 0));
+        assert_eq!(echo_event.start_timestamp, None);
+    }
+
+    #[test]
+    fn timestamp_iso8601() -> Result<()> {
+        let mut echo_event = routed_event(EventType::Info);
+        let _ = echo_event.set_timestamp_iso8601(Utc.ymd(1976, 3, 22).and_hms_milli(0, 0, 1, 666));
+        assert_eq!(echo_event.timestamp(), Some(196_300_801_666));
+
+        let json = serde_json::to_string(&echo_event)?;
+        assert_eq!(
+            json,
+            r#"{"routingKey":"atlas-dev-promises","type":"INFO","message":"","timestamp":"1976-03-22T00:00:01.666Z"}"#
+        );
+        assert_eq!(serde_json::from_str::<Event>(&json)?, echo_event);
+        Ok(())
+    }
+
+    #[test]
+    fn mark_start_finish() -> Result<()> {
+        let mut echo_event = Event::default();
+        assert!(echo_event.mark_finish().is_err());
+        assert_eq!(echo_event.finish_timestamp, None);
+
+        let _ = echo_event.set_start_timestamp_dt(Utc.ymd(1976, 3, 22).and_hms(0, 0, 1));
+        let _ = echo_event.finish_at(Utc.ymd(1976, 3, 22).and_hms_milli(0, 0, 2, 500))?;
+        assert_eq!(echo_event.finish_timestamp, Some(196_300_802_500));
+        assert_eq!(echo_event.duration, Some(1500));
+        assert_eq!(echo_event.duration_in_ms, Some(1500));
+        assert!(echo_event
+            .finish_at(Utc.ymd(1976, 3, 22).and_hms(0, 0, 0))
+            .is_err());
+        assert_eq!(echo_event.duration, Some(1500));
+
+        let _ = echo_event.mark_start().mark_finish()?;
+        assert!(echo_event.duration.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn check_response_fields() {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_event_type(EventType::Performance);
+        let _ = echo_event.set_response_code(Some(200));
+        assert!(echo_event.check_response_fields().is_ok());
+
+        let _ = echo_event.set_event_type(EventType::System);
+        assert!(echo_event.check_response_fields().is_err());
+
+        let _ = echo_event.set_response_code(None);
+        let _ = echo_event.set_response(Some(Response::Success));
+        assert!(echo_event.check_response_fields().is_err());
+
+        let _ = echo_event.set_response(None);
+        assert!(echo_event.check_response_fields().is_ok());
+    }
+
+    #[test]
+    fn render_template() {
+        let mut echo_event = Event::default();
+        let mut message_detail = HashMap::new();
+        let _ = message_detail.insert("action".to_string(), "login".to_string());
+        let _ = message_detail.insert("user".to_string(), "yoda".to_string());
+        let _ = echo_event.set_message_detail(Some(message_detail));
+
+        assert_eq!(
+            echo_event.render_template("{action} failed for {user}"),
+            "login failed for yoda"
+        );
+        assert_eq!(
+            echo_event.render_template("{action} failed at {missing}"),
+            "login failed at "
+        );
+        assert_eq!(
+            echo_event.render_template("unclosed {user"),
+            "unclosed {user"
+        );
+    }
+
+    #[test]
+    fn check_direction() {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_client_host_name(Some("blah"));
+        assert!(echo_event.check_direction().is_ok());
+
+        let _ = echo_event.set_destination_path(Some("yoda"));
+        assert!(echo_event.check_direction().is_err());
+
+        let _ = echo_event.set_client_host_name::<String>(None);
+        assert!(echo_event.check_direction().is_ok());
+    }
+
+    fn body_string(req: Request<Body>) -> Result<String> {
+        let bytes = block_on(hyper::body::to_bytes(req.into_body()))?;
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn without_stamping(echo_spawner: &mut Spawner) {
+        let _ = echo_spawner
+            .set_auto_timestamp(false)
+            .set_default_host(None);
+    }
+
+    fn routed_event(event_type: EventType) -> Event {
+        let mut echo_event = Event::default();
+        let _ = echo_event.set_routing_key("atlas-dev-promises");
+        let _ = echo_event.set_event_type(event_type);
+        echo_event
+    }
+
+    #[test]
+    fn to_request() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner.set_data_center(Some("cdc".to_string()));
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+
+        let req = payload.to_request(&echo_spawner)?;
+        let expected = r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","dataCenter":"cdc"}]"#;
+        assert_eq!(req.method(), "POST");
+        assert_eq!(
+            req.uri(),
+            "https://echocollector-stage.kroger.com/echo/messages"
+        );
+        assert_eq!(req.headers()["Content-Type"], "application/json");
+        assert_eq!(
+            req.headers()["Content-Length"],
+            expected.len().to_string().as_str()
+        );
+        assert!(req.headers().get("Content-Encoding").is_none());
+        assert_eq!(body_string(req)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn tenant_id() -> Result<()> {
+        let mut echo_spawner = Spawner::for_tenant("acme")?;
+        without_stamping(&mut echo_spawner);
+
+        let mut other_tenant = routed_event(EventType::Info);
+        let _ = other_tenant.set_tenant_id(Some("globex"));
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info), other_tenant]);
+
+        let req = payload.to_request(&echo_spawner)?;
+        let expected = r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","tenantId":"acme"},{"routingKey":"atlas-dev-promises","type":"INFO","message":"","tenantId":"acme"}]"#;
+        assert_eq!(body_string(req)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_events() -> Result<()> {
+        let mut payload = Payload::default();
+        let (json, errors) = payload.serialize_events();
+        assert_eq!(json, "[]");
+        assert!(errors.is_empty());
+
+        let _ = payload
+            .add_event(routed_event(EventType::Info))
+            .add_event(routed_event(EventType::Error));
+        let (json, errors) = payload.serialize_events();
+        assert_eq!(json, serde_json::to_string(&payload.events)?);
+        assert!(errors.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn add_event() {
+        let mut payload = Payload::default();
+        let _ = payload
+            .add_event(routed_event(EventType::Info))
+            .add_event(routed_event(EventType::Tracking))
+            .add_event(routed_event(EventType::Error));
+        assert_eq!(payload.event_count(), 3);
+        assert_eq!(payload.events[1].event_type, EventType::Tracking);
+
+        let _ = payload.extend_events(vec![routed_event(EventType::System); 2]);
+        assert_eq!(payload.event_count(), 5);
+    }
+
+    #[test]
+    fn rejected_subset() -> Result<()> {
+        let mut payload = Payload::default();
+        let _ = payload.set_events(
+            (0..3)
+                .map(|i| {
+                    let mut echo_event = routed_event(EventType::Info);
+                    let _ = echo_event.set_message(format!("Message {}", i));
+                    echo_event
+                })
+                .collect(),
+        );
+
+        let rejection: BatchRejection =
+            serde_json::from_str(r#"{"rejected":[{"index":2},{"index":0}]}"#)?;
+        let subset = payload.rejected_subset(&rejection);
+        let messages: Vec<&str> = subset.events.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["Message 0", "Message 2"]);
+        assert_eq!(subset.retry_count, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn single_event_path() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner.set_single_event_path(Some("/echo/message".to_string()));
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(
+            req.uri(),
+            "https://echocollector-stage.kroger.com/echo/message"
+        );
+        assert_eq!(
+            body_string(req)?,
+            r#"{"routingKey":"atlas-dev-promises","type":"INFO","message":""}"#
+        );
+
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Error),
+        ]);
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(
+            req.uri(),
+            "https://echocollector-stage.kroger.com/echo/messages"
+        );
+        assert!(body_string(req)?.starts_with('['));
+        Ok(())
+    }
+
+    #[test]
+    fn pause_and_resume() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        echo_spawner.pause();
+        assert!(echo_spawner.is_paused());
+        echo_spawner.spawn(&payload)?;
+        let _ = echo_spawner.set_pause_policy(PausePolicy::Drop);
+        echo_spawner.spawn(&payload)?;
+        assert_eq!(echo_spawner.paused_buffered(), 1);
+        assert_eq!(echo_spawner.paused_dropped(), 1);
+        assert!(!echo_spawner.metrics_text().contains("echo_events_total"));
+
+        let _ = echo_spawner
+            .set_pause_policy(PausePolicy::Buffer)
+            .set_pause_buffer_capacity(Some(1));
+        echo_spawner.spawn(&payload)?;
+        assert_eq!(echo_spawner.paused_buffered(), 2);
+        assert_eq!(echo_spawner.paused_dropped(), 2);
+
+        echo_spawner.resume()?;
+        assert!(!echo_spawner.is_paused());
+        assert!(echo_spawner
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 1"#));
+        Ok(())
+    }
+
+    #[test]
+    fn transient_errors() {
+        assert!(is_transient(&ErrKind::Timeout));
+        assert!(is_transient(&ErrKind::Run {
+            status: 503,
+            body: String::new(),
+            retry_after: None,
+        }));
+        assert!(is_transient(&ErrKind::Run {
+            status: 429,
+            body: String::new(),
+            retry_after: None,
+        }));
+        assert!(!is_transient(&ErrKind::Run {
+            status: 400,
+            body: String::new(),
+            retry_after: None,
+        }));
+        assert!(!is_transient(&ErrKind::AmbiguousDirection));
+        assert!(!is_transient(&ErrKind::Rejected(BatchRejection::default())));
+    }
+
+    #[test]
+    fn send_without_request() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        echo_spawner.send(&Payload::default())?;
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![Event::default()]);
+        assert!(echo_spawner.send(&payload).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn custom_url() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let url = "http://localhost:8080/echo/messages";
+
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(url.to_string()))
+            .set_events(vec![routed_event(EventType::Info)]);
+        assert_eq!(payload.to_request(&echo_spawner)?.uri(), url);
+        Ok(())
+    }
+
+    #[test]
+    fn try_set_routing_key() -> Result<()> {
+        let mut echo_event = Event::default();
+        assert!(echo_event.try_set_routing_key("").is_err());
+        assert!(echo_event
+            .try_set_routing_key("Atlas-dev-promises")
+            .is_err());
+        assert!(echo_event
+            .try_set_routing_key("atlas dev-promises")
+            .is_err());
+        assert!(echo_event.try_set_routing_key("atlas-promises").is_err());
+        assert!(echo_event.try_set_routing_key("atlas--promises").is_err());
+        assert_eq!(echo_event.routing_key, "");
+
+        let _ = echo_event.try_set_routing_key("atlas-dev-promises2")?;
+        assert_eq!(echo_event.routing_key, "atlas-dev-promises2");
+        Ok(())
+    }
+
+    #[test]
+    fn getters() -> Result<()> {
+        let correlation_id = Uuid::new_v4();
+        let mut echo_event = routed_event(EventType::Error);
+        let _ = echo_event.set_correlation_id(Some(correlation_id));
+
+        assert_eq!(echo_event.routing_key(), "atlas-dev-promises");
+        assert_eq!(echo_event.event_type(), &EventType::Error);
+        assert_eq!(echo_event.correlation_id(), &Some(correlation_id));
+        assert_eq!(echo_event.timestamp(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn payload_headers() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let mut headers = HashMap::new();
+        let _ = headers.insert("X-Tenant-Id".to_string(), "acme".to_string());
+        let _ = headers.insert("Content-Type".to_string(), "text/plain".to_string());
+
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_headers(headers)
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.headers()["X-Tenant-Id"], "acme");
+        assert_eq!(req.headers()["Content-Type"], "application/json");
+        Ok(())
+    }
+
+    #[test]
+    fn bearer_token() -> Result<()> {
+        let echo_spawner = Spawner::with_bearer_token("first")?;
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.headers()["Authorization"], "Bearer first");
+
+        echo_spawner.set_token("rotated");
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.headers()["Authorization"], "Bearer rotated");
+        assert!(!format!("{:?}", echo_spawner).contains("rotated"));
+
+        let req = payload.to_request(&Spawner::new()?)?;
+        assert!(req.headers().get("Authorization").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn clone_shares_state() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner.set_sequence_events(true);
+        let cloned = echo_spawner.clone();
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        let first = body_string(payload.to_request(&echo_spawner)?)?;
+        let second = body_string(payload.to_request(&cloned)?)?;
+        assert!(first.contains(r#""sequence":0"#));
+        assert!(second.contains(r#""sequence":1"#));
+
+        cloned.set_default_url(CollectorUrl::Custom("http://localhost:8080/echo".into()));
+        assert_eq!(
+            echo_spawner.default_url(),
+            Some(CollectorUrl::Custom("http://localhost:8080/echo".into()))
+        );
+
+        cloned.pause();
+        assert!(echo_spawner.is_paused());
+        echo_spawner.resume()?;
+        Ok(())
+    }
+
+    #[test]
+    fn shutdown_refuses_sends() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let cloned = echo_spawner.clone();
+        echo_spawner.shutdown(Duration::from_millis(10))?;
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+
+        assert!(cloned.spawn(&payload).is_err());
+        assert!(cloned.send(&payload).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn validate() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut timed = routed_event(EventType::Performance);
+        let _ = timed
+            .set_start_timestamp(Some(2))
+            .set_finish_timestamp(Some(1));
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info), Event::default(), timed]);
+        assert!(payload.to_request(&echo_spawner).is_err());
+
+        let problems = format!("{:?}", payload.validate());
+        assert!(problems.contains("event 1: empty routing key"));
+        assert!(problems.contains("event 2: finish timestamp 1 precedes"));
+
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        let _ = echo_spawner.set_strict_validation(true);
+        assert!(payload.validate().is_ok());
+        assert!(payload.to_request(&echo_spawner).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn collector_url_from_env() -> Result<()> {
+        let stage = || Ok("Stage".to_string());
+        assert_eq!(
+            CollectorUrl::from_vars(Some("http://localhost:8080/echo".to_string()), stage())?,
+            CollectorUrl::Custom("http://localhost:8080/echo".to_string())
+        );
+        assert_eq!(
+            CollectorUrl::from_vars(Some(" ".to_string()), stage())?,
+            CollectorUrl::Stage
+        );
+        assert_eq!(
+            CollectorUrl::from_vars(None, Ok("prod".to_string()))?,
+            CollectorUrl::Prod
+        );
+
+        let unset = CollectorUrl::from_vars(None, Err(VarError::NotPresent));
+        assert!(matches!(
+            unset.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::Var(VarError::NotPresent))
+        ));
+        let unknown = CollectorUrl::from_vars(None, Ok("dev".to_string()));
+        assert!(matches!(
+            unknown.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::Str(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_system_events() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut payload = Payload::default();
+        let _ = payload
+            .add_event(routed_event(EventType::Info))
+            .add_event(routed_event(EventType::System));
+        assert!(payload.to_request(&echo_spawner).is_ok());
+
+        let _ = echo_spawner.set_reject_system_events(true);
+        let req = payload.to_request(&echo_spawner);
+        assert!(matches!(
+            req.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::ReservedEventType(1))
+        ));
+        assert!(payload.to_request(&echo_spawner.system_spawner()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn with_config() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config
+            .set_dns_threads(1)
+            .set_pool_max_idle_per_host(2)
+            .set_pool_idle_timeout(None);
+        let echo_spawner = Spawner::with_config(&config)?;
+
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+        assert!(echo_spawner.spawn(&payload).is_ok());
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct Unreachable;
+
+    impl DataCenterResolver for Unreachable {
+        fn request(&self, _token: Option<&str>) -> Result<hyper::Request<hyper::Body>> {
+            Err("no metadata service".into())
+        }
+
+        fn parse(&self, _body: &str) -> Option<String> {
+            None
+        }
+    }
+
+    #[test]
+    fn with_config_combines_options() -> Result<()> {
+        let url = CollectorUrl::Custom("http://localhost:8080/echo".into());
+        let mut config = SpawnerConfig::default();
+        let _ = config
+            .set_tenant_id(Some("acme".into()))
+            .set_bearer_token("token")
+            .set_logger(Some(Logger::root(slog::Discard, o!())))
+            .set_default_url(Some(url.clone()))
+            .set_data_center_resolver(Unreachable)
+            .set_data_center(Some("us-east-1".into()));
+        let echo_spawner = Spawner::with_config(&config)?;
+
+        assert_eq!(echo_spawner.tenant_id.as_deref(), Some("acme"));
+        assert_eq!(echo_spawner.bearer_token().as_deref(), Some("token"));
+        assert!(echo_spawner.logger_for(&Payload::default()).is_some());
+        assert_eq!(echo_spawner.default_url(), Some(url));
+        assert_eq!(echo_spawner.data_center.as_deref(), Some("us-east-1"));
+        Ok(())
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn with_invalid_identity() {
+        assert!(Spawner::with_identity(b"not a pkcs12 archive", "password").is_err());
+    }
+
+    #[test]
+    fn with_invalid_pem_identity() {
+        assert!(Spawner::with_pem_identity(b"not a certificate", b"not a key").is_err());
+    }
+
+    #[test]
+    fn auto_correlate() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_auto_correlate(true);
+        let correlated = Uuid::new_v4();
+        let mut with_id = routed_event(EventType::Info);
+        let _ = with_id.set_correlation_id(Some(correlated));
+
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![
+                routed_event(EventType::Info),
+                with_id,
+                routed_event(EventType::Error),
+            ]);
+
+        let (events, _, generated) = payload.prepare_correlated(&echo_spawner)?;
+        assert!(generated.is_some());
+        assert_eq!(events[0].correlation_id, generated);
+        assert_eq!(events[1].correlation_id, Some(correlated));
+        assert_eq!(events[2].correlation_id, generated);
+        assert!(echo_spawner.spawn_correlated(&payload)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn persistable_payload() -> Result<()> {
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://localhost:8080/echo".into()))
+            .set_events(vec![routed_event(EventType::Error)])
+            .set_retry_count(3);
+        payload.error_count = 1;
+
+        let json = serde_json::to_string(&PersistablePayload::from(&payload))?;
+        let replayed = Payload::from(serde_json::from_str::<PersistablePayload>(&json)?);
+        assert_eq!(replayed.url, payload.url);
+        assert_eq!(replayed.events, payload.events);
+        assert_eq!(replayed.error_count, 1);
+        assert_eq!(replayed.retry_count, 3);
+        assert!(replayed.logger.is_none());
+
+        let stage: CollectorUrl =
+            serde_json::from_str(&serde_json::to_string(&CollectorUrl::Stage)?)?;
+        assert_eq!(stage, CollectorUrl::Stage);
+        Ok(())
+    }
+
+    #[test]
+    fn request_after_shutdown() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let cloned = echo_spawner.clone();
+        echo_spawner.shutdown(Duration::from_millis(10))?;
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        let mut rt = tokio::runtime::Runtime::new()?;
+        assert!(rt.block_on(cloned.request(&payload)).is_err());
+
+        let _ = payload.set_events(vec![]);
+        assert!(rt.block_on(Spawner::new()?.request(&payload)).is_ok());
+        Ok(())
+    }
+
+    #[derive(Debug, Default)]
+    struct CountingMetrics {
+        successes: AtomicUsize,
+        failures: AtomicUsize,
+    }
+
+    impl Metrics for CountingMetrics {
+        fn on_success(&self, _latency: Duration, _bytes: usize) {
+            let _ = self.successes.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_failure(&self, status: Option<u16>) {
+            assert_eq!(status, None);
+            let _ = self.failures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn metrics() -> Result<()> {
+        let metrics = Arc::new(CountingMetrics::default());
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_metrics(metrics.clone());
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        // Nothing listens on port 1, so the send fails without a status
+        assert!(echo_spawner.send(&payload).is_err());
+        assert_eq!(metrics.successes.load(Ordering::SeqCst), 0);
+        assert_eq!(metrics.failures.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn render() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner
+            .set_data_center(Some("cdc".to_string()))
+            .set_compression(true)
+            .set_dry_run(true);
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        assert_eq!(
+            echo_spawner.render(&payload)?,
+            r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","dataCenter":"cdc"}]"#
+        );
+        // Nothing listens on port 1, so only a dry run succeeds
+        assert!(echo_spawner.send(&payload).is_ok());
+        assert!(echo_spawner.spawn(&payload).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn ndjson() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_events(vec![
+                routed_event(EventType::Info),
+                routed_event(EventType::Error),
+            ])
+            .set_format(PayloadFormat::Ndjson);
+
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.headers()["Content-Type"], "application/x-ndjson");
+        let body = body_string(req)?;
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(body.ends_with('\n'));
+
+        for (line, event_type) in lines.iter().zip(&["INFO", "ERROR"]) {
+            let event: serde_json::Value = serde_json::from_str(line)?;
+            assert_eq!(event["type"], *event_type);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_grouped() -> Result<()> {
+        let custom = CollectorUrl::Custom("http://127.0.0.1:1/errors".into());
+        let route = |event: &Event| {
+            if event.event_type == EventType::Error {
+                custom.clone()
+            } else {
+                CollectorUrl::Custom("http://127.0.0.1:1/echo".into())
+            }
+        };
+        let events = vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Error),
+            routed_event(EventType::Tracking),
+        ];
+
+        let payloads = group_by_url(events.clone(), route);
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(
+            payloads[0].url,
+            CollectorUrl::Custom("http://127.0.0.1:1/echo".into())
+        );
+        assert_eq!(
+            payloads[0].events,
+            vec![events[0].clone(), events[2].clone()]
+        );
+        assert_eq!(payloads[1].url, custom);
+        assert_eq!(payloads[1].events, vec![events[1].clone()]);
+
+        assert!(Spawner::new()?.spawn_grouped(events, route).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn idempotency_key() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        assert!(payload
+            .to_request(&echo_spawner)?
+            .headers()
+            .get("Idempotency-Key")
+            .is_none());
+
+        let _ = echo_spawner.set_idempotency(true);
+        let key = |payload: &Payload| -> Result<hyper::header::HeaderValue> {
+            let req = payload.to_request(&echo_spawner)?;
+            Ok(req.headers()["Idempotency-Key"].clone())
+        };
+        let first = key(&payload)?;
+        assert_eq!(first.len(), 64);
+        assert_eq!(key(&payload.clone())?, first);
+
+        let _ = payload.set_events(vec![routed_event(EventType::Error)]);
+        assert_ne!(key(&payload)?, first);
+        Ok(())
+    }
+
+    #[test]
+    fn idempotency_key_across_retries() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner
+            .set_idempotency(true)
+            .set_auto_timestamp(true)
+            .set_sequence_events(true);
+
+        // Fails the first two attempts of every three
+        let keys = Arc::new(Mutex::new(Vec::<String>::new()));
+        let recorded = keys.clone();
+        let make_service = make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let mut keys = recorded.lock().expect("recorded keys");
+                    keys.extend(
+                        req.headers()
+                            .get("Idempotency-Key")
+                            .and_then(|key| key.to_str().ok())
+                            .map(str::to_string),
+                    );
+                    let status = if keys.len() % 3 == 0 {
+                        StatusCode::OK
+                    } else {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    };
+                    async move {
+                        hyper::Response::builder()
+                            .status(status)
+                            .body(Body::empty())
+                    }
+                }))
+            }
+        });
+        let handle = echo_spawner.handle();
+        let server = handle.enter(|| Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service));
+        let addr = server.local_addr();
+        let _ = handle.spawn(server);
+
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)])
+            .set_retry_count(2);
+        echo_spawner.send_with_retry(&payload, 2, Duration::from_millis(1))?;
+        echo_spawner.spawn(&payload)?;
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+
+        let keys = keys.lock().map_err(|_| "recorded keys poisoned")?;
+        assert_eq!(keys.len(), 6);
+        assert!(keys[..3].iter().all(|key| key == &keys[0]));
+        assert!(keys[3..].iter().all(|key| key == &keys[3]));
+        assert_ne!(keys[0], keys[3]);
+        Ok(())
+    }
+
+    #[test]
+    fn response_status() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, _) = local_collector(&echo_spawner.handle(), false, |path, _| {
+            if path == "/queued" {
+                (StatusCode::ACCEPTED, String::new())
+            } else if path == "/huge" {
+                let padding = " ".repeat(MAX_ACK_BODY_BYTES);
+                (StatusCode::OK, format!(r#"{{"accepted":1{}}}"#, padding))
+            } else {
+                (StatusCode::OK, r#"{"accepted":1}"#.to_string())
+            }
+        });
+        let ack = |path: &str| -> Result<BatchAck> {
+            let mut payload = Payload::default();
+            let _ = payload
+                .set_url(CollectorUrl::Custom(format!("http://{}{}", addr, path)))
+                .set_events(vec![routed_event(EventType::Info)]);
+            Ok(block_on(echo_spawner.spawn_with_response(&payload)?)
+                .map_err(|_| "no acknowledgement")?)
+        };
+
+        let queued = ack("/queued")?;
+        assert_eq!(*queued.status(), Some(202));
+        assert_eq!(*queued.accepted(), 0);
+
+        let processed = ack("/echo")?;
+        assert_eq!(*processed.status(), Some(200));
+        assert_eq!(*processed.accepted(), 1);
+
+        // Success bodies are capped, so an oversized body isn't an acknowledgement
+        assert!(ack("/huge").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn circuit_breaker() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_circuit_threshold(Some(2));
+        let echo_spawner = Spawner::with_config(&config)?;
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".to_string()))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        assert!(echo_spawner.send(&payload).is_err());
+        assert_eq!(echo_spawner.circuit_state(), CircuitState::Closed);
+        assert!(echo_spawner.send(&payload).is_err());
+        assert_eq!(echo_spawner.circuit_state(), CircuitState::Open);
+
+        // Fails immediately, without a network call
+        let spawned = echo_spawner.spawn(&payload);
+        assert!(matches!(
+            spawned.as_ref().map_err(crate::error::Err::kind),
+            Err(ErrKind::CircuitOpen)
+        ));
+        assert_eq!(Spawner::new()?.circuit_state(), CircuitState::Closed);
+        Ok(())
+    }
+
+    #[test]
+    fn user_agent() -> Result<()> {
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        let crate_agent = format!("libechoexec/{}", env!("CARGO_PKG_VERSION"));
+
+        let req = payload.to_request(&Spawner::new()?)?;
+        assert_eq!(req.headers()["User-Agent"], crate_agent.as_str());
+
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_user_agent(Some("myapp/1.0".to_string()));
+        let req = payload.to_request(&Spawner::with_config(&config)?)?;
+        assert_eq!(
+            req.headers()["User-Agent"],
+            format!("myapp/1.0 {}", crate_agent).as_str()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn retry_after() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, count| {
+            if count == 0 {
+                (StatusCode::TOO_MANY_REQUESTS, String::new())
+            } else {
+                (StatusCode::OK, String::new())
+            }
+        });
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        // The collector's Retry-After is honored instead of the minute long backoff
+        let start = std::time::Instant::now();
+        echo_spawner.send_with_retry(&payload, 1, Duration::from_secs(60))?;
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Long delays are clamped
+        let throttled = ErrKind::Run {
+            status: 429,
+            body: String::new(),
+            retry_after: Some(Duration::from_secs(3600)),
+        };
+        assert_eq!(
+            retry_delay(&throttled, Duration::from_secs(30)),
+            Some(Duration::from_secs(30))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_warm_stops_on_shutdown() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let url = CollectorUrl::Custom(format!("http://{}/echo", addr));
+        echo_spawner.keep_warm(url, Duration::from_millis(10))?;
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(requests.load(Ordering::SeqCst) > 0);
+
+        // Shutting down a clone leaves the runtime running, but stops the requests
+        echo_spawner.clone().shutdown(Duration::from_millis(10))?;
+        let stopped = requests.load(Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(requests.load(Ordering::SeqCst) <= stopped + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn warm_up() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::NOT_FOUND, String::new())
+        });
+
+        echo_spawner.warm_up(CollectorUrl::Custom(format!("http://{}/echo", addr)))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(echo_spawner
+            .warm_up(CollectorUrl::Custom("http://127.0.0.1:1/echo".to_string()))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_event() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let url = CollectorUrl::Custom(format!("http://{}/echo", addr));
+
+        echo_spawner.spawn_event_to(url, routed_event(EventType::Info))?;
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn shutdown_flushes_paused() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        echo_spawner.pause();
+        echo_spawner.spawn(&payload)?;
+        echo_spawner.spawn(&payload)?;
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn shutdown_summary_skips_filters() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let url = CollectorUrl::Custom(format!("http://{}/echo", addr));
+        let mut sampling = SamplingPolicy::new();
+        let _ = sampling.set_rate(EventType::System, 0.0);
+        let _ = echo_spawner
+            .set_min_severity(Some(EventType::Error))
+            .set_sampling(Some(sampling))
+            .set_shutdown_summary(Some(ShutdownSummary::new(url, "atlas-dev-promises")));
+
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // Nothing is sent when preparing the summary leaves no event
+        let mut echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let url = CollectorUrl::Custom(format!("http://{}/echo", addr));
+        let _ = echo_spawner
+            .set_empty_routing_key(InvalidEventPolicy::Drop)
+            .set_shutdown_summary(Some(ShutdownSummary::new(url, "")));
+
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn byte_budget_encodes_after_acquiring() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let _ = echo_spawner.set_max_in_flight_bytes(Some(1024));
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        echo_spawner.send(&payload)?;
+        echo_spawner.spawn(&payload)?;
+
+        // The body is only encoded once the budget is acquired, so encoding errors come
+        // from the send
+        let _ = echo_spawner.set_max_payload_bytes(Some(1));
+        echo_spawner.spawn(&payload)?;
+        assert!(matches!(
+            echo_spawner
+                .send(&payload)
+                .as_ref()
+                .map_err(crate::error::Err::kind),
+            Err(ErrKind::PayloadTooLarge { .. })
+        ));
+        echo_spawner.shutdown(Duration::from_secs(5))?;
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn ping() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), false, |path, _| {
+            if path == "/down" {
+                (StatusCode::SERVICE_UNAVAILABLE, String::new())
+            } else {
+                (StatusCode::OK, String::new())
+            }
+        });
+        let url = |path: &str| CollectorUrl::Custom(format!("http://{}{}", addr, path));
+
+        assert!(echo_spawner.ping(url("/echo"))?);
+        assert!(!echo_spawner.ping(url("/down"))?);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(echo_spawner
+            .ping(CollectorUrl::Custom("http://127.0.0.1:1/echo".to_string()))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn http2_prior_knowledge() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_http2_prior_knowledge(true);
+        let echo_spawner = Spawner::with_config(&config)?;
+        let http1_spawner = Spawner::new()?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), true, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        // The collector only speaks HTTP/2
+        assert!(echo_spawner.send(&payload).is_ok());
+        assert!(http1_spawner.send(&payload).is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn http2_only() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_http2_only(true);
+        let echo_spawner = Spawner::with_config(&config)?;
+        let (addr, requests) = local_collector(&echo_spawner.handle(), true, |_, _| {
+            (StatusCode::OK, String::new())
+        });
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        // The `tls` backend can't negotiate HTTP/2, so it speaks it without negotiating
+        assert!(echo_spawner.send(&payload).is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn error_body_cap() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_max_error_body_bytes(1024);
+        let (addr, _) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (StatusCode::INTERNAL_SERVER_ERROR, "x".repeat(100_000))
+        });
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        let err = echo_spawner.send(&payload).err();
+
+        match err.as_ref().map(crate::error::Err::kind) {
+            Some(ErrKind::Run { status, body, .. }) => {
+                assert_eq!(*status, 500);
+                assert_eq!(*body, format!("{}... (truncated)", "x".repeat(1024)));
+            }
+            _ => panic!("expected an error response"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn send_with_retry() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let (addr, requests) = flaky_collector(&echo_spawner.handle());
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        assert!(echo_spawner
+            .send_with_retry(&payload, 1, Duration::from_millis(1))
+            .is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(echo_spawner
+            .send_with_retry(&payload, 3, Duration::from_millis(1))
+            .is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // The payload is prepared once per send, however many attempts it takes
+        assert!(echo_spawner
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 2"#));
+
+        // Client errors aren't retried
+        let _ = payload.set_url(CollectorUrl::Custom(format!("http://{}/bad", addr)));
+        assert!(echo_spawner
+            .send_with_retry(&payload, 3, Duration::from_millis(1))
+            .is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn handle() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let task = echo_spawner.handle().spawn(async { 42 });
+        assert_eq!(block_on(task).map_err(|e| e.to_string())?, 42);
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_all() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let mut payload = Payload::default();
+        let mut payloads = vec![payload.clone(); 3];
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+        payloads.insert(1, payload);
+
+        // Nothing listens on port 1, so only the empty payloads succeed
+        let mut rt = tokio::runtime::Runtime::new()?;
+        let results = rt.block_on(echo_spawner.spawn_all(payloads, 2));
+        let succeeded: Vec<bool> = results.iter().map(|result| result.is_ok()).collect();
+        assert_eq!(succeeded, vec![true, false, true, true]);
+        Ok(())
     }
 
-    /// Set the destination host name
-    pub fn set_destination_host_name<T>(&mut self, destination_host_name: Option<T>) -> &mut Self
-    where
-        T: Into<String>,
-    {
-        self.destination_host_name = match destination_host_name {
-            None => None,
-            Some(t) => Some(t.into()),
-        };
-        self
+    #[test]
+    fn sink() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let mut sink = Box::pin(echo_spawner.sink(1));
+        let mut payload = Payload::default();
+        assert!(block_on(sink.send(payload.clone())).is_ok());
+
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+        assert!(block_on(sink.send(payload.clone())).is_ok());
+        assert!(block_on(sink.close()).is_ok());
+
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_max_payload_bytes(Some(1));
+        let mut sink = Box::pin(echo_spawner.sink(1));
+        assert!(block_on(sink.send(payload)).is_err());
+        Ok(())
     }
 
-    /// Set the destination path
-    pub fn set_destination_path<T>(&mut self, destination_path: Option<T>) -> &mut Self
-    where
-        T: Into<String>,
-    {
-        self.destination_path = match destination_path {
-            None => None,
-            Some(t) => Some(t.into()),
-        };
-        self
+    #[test]
+    fn send_paths_share_guards() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_max_requests_per_sec(Some(1));
+        let _ = config.set_rate_limit_policy(RateLimitPolicy::Reject);
+        let echo_spawner = Spawner::with_config(&config)?;
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
+
+        // Every path is held by the pause gate
+        echo_spawner.pause();
+        echo_spawner.send(&payload)?;
+        block_on(echo_spawner.request(&payload))?;
+        assert!(block_on(echo_spawner.spawn_with_response(&payload)?).is_err());
+        assert_eq!(echo_spawner.paused_buffered(), 3);
+        assert_eq!(echo_spawner.pause_gate.resume().len(), 3);
+
+        // And by the rate limit
+        let _ = echo_spawner.spawn_with_response(&payload)?;
+        assert!(matches!(
+            block_on(echo_spawner.request(&payload))
+                .as_ref()
+                .map_err(crate::error::Err::kind),
+            Err(ErrKind::RateLimited)
+        ));
+        assert!(echo_spawner.send(&payload).is_err());
+        Ok(())
     }
-}
 
-/// Echo Event Type
-///
-/// The following types are currently recognized:
-///
-/// * ERROR - Any message that should be associated with a non-normal action or situation that the system processed.
-/// * INFO - Any message that should be associated with a normal action or situation that the system processed.
-/// * PERFORMANCE - Any message that associates speed or time taken with which any action or situation that the system processed.
-/// * TRACKING - Any message that tries to correlate two (or more) events or data points that is not associated.
-/// * SYSTEM - Internally used for client machine performance data (CPU utilization, JVM heap usage, ect)
-///
-/// Additional types may be added in the future.
-///
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum EventType {
-    /// ERROR
-    Error,
-    /// INFO
-    Info,
-    /// PERFORMANCE
-    Performance,
-    /// TRACKING
-    Tracking,
-    /// SYSTEM
-    System,
-}
+    #[test]
+    fn rate_limited() -> Result<()> {
+        let mut config = SpawnerConfig::default();
+        let _ = config.set_max_requests_per_sec(Some(1));
+        let _ = config.set_rate_limit_policy(RateLimitPolicy::Reject);
+        let echo_spawner = Spawner::with_config(&config)?;
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()))
+            .set_events(vec![routed_event(EventType::Info)]);
 
-impl Default for EventType {
-    fn default() -> Self {
-        Self::Info
+        assert_eq!(echo_spawner.available_requests(), Some(1));
+        assert!(echo_spawner.spawn(&payload).is_ok());
+        assert_eq!(echo_spawner.available_requests(), Some(0));
+        assert!(echo_spawner.spawn(&payload).is_err());
+        assert_eq!(Spawner::new()?.available_requests(), None);
+        Ok(())
     }
-}
 
-impl Ser for EventType {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match *self {
-            Self::Error => serializer.serialize_str("ERROR"),
-            Self::Info => serializer.serialize_str("INFO"),
-            Self::Performance => serializer.serialize_str("PERFORMANCE"),
-            Self::Tracking => serializer.serialize_str("TRACKING"),
-            Self::System => serializer.serialize_str("SYSTEM"),
-        }
+    #[test]
+    fn default_url() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+
+        assert_eq!(echo_spawner.default_url(), None);
+        echo_spawner.set_default_url(CollectorUrl::Prod);
+        assert_eq!(echo_spawner.default_url(), Some(CollectorUrl::Prod));
+
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.uri(), "https://echocollector.kroger.com/echo/messages");
+        Ok(())
     }
-}
 
-impl<'de> Deser<'de> for EventType {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_str(EventTypeVisitor)
+    #[test]
+    fn min_severity() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_min_severity(Some(EventType::Info));
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Tracking),
+            routed_event(EventType::Info),
+            routed_event(EventType::Error),
+        ]);
+
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, EventType::Info);
+        assert_eq!(events[1].event_type, EventType::Error);
+        assert_eq!(echo_spawner.filtered(), 1);
+        Ok(())
     }
-}
 
-struct EventTypeVisitor;
+    #[test]
+    fn sampling() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut sampling = SamplingPolicy::new();
+        let _ = sampling.set_rate(EventType::Info, 0.0);
+        let _ = echo_spawner.set_sampling(Some(sampling));
 
-impl Visitor<'_> for EventTypeVisitor {
-    type Value = EventType;
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Error),
+            routed_event(EventType::Info),
+        ]);
 
-    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("one of ERROR, INFO, PERFORMANCE, TRACKING or SYSTEM")
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, EventType::Error);
+        assert_eq!(echo_spawner.sampled(), 2);
+        Ok(())
     }
 
-    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-    where
-        E: de::Error,
-    {
-        Ok(match value {
-            "ERROR" => EventType::Error,
-            "INFO" => EventType::Info,
-            "PERFORMANCE" => EventType::Performance,
-            "TRACKING" => EventType::Tracking,
-            "SYSTEM" => EventType::System,
-            _ => return Err(E::custom(format!("invalid event type: {}", value))),
-        })
+    #[test]
+    fn empty_routing_key() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            Event::default(),
+            routed_event(EventType::Error),
+        ]);
+
+        assert!(payload.prepare_events(&echo_spawner).is_err());
+
+        let _ = echo_spawner.set_empty_routing_key(InvalidEventPolicy::Drop);
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, EventType::Info);
+        assert_eq!(events[1].event_type, EventType::Error);
+        Ok(())
     }
-}
 
-/// A more generic response used when a HTTP response code doesn't make sense. Typical values might be "success" or "failure".
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum Response {
-    /// Success
-    Success,
-    /// Failure
-    Failure,
-}
+    #[test]
+    fn max_payload_bytes() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner.set_max_payload_bytes(Some(100));
 
-impl Default for Response {
-    fn default() -> Self {
-        Self::Success
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info)]);
+        assert!(payload.to_request(&echo_spawner).is_ok());
+
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Info),
+        ]);
+        assert!(payload.to_request(&echo_spawner).is_err());
+        assert!(echo_spawner.spawn(&payload).is_err());
+
+        // Batches counted over the cap fail before the body is built
+        let event_len = crate::body::json_len(&routed_event(EventType::Info))?;
+        let _ = payload.set_events(vec![routed_event(EventType::Info); 4]);
+        assert!(matches!(
+            payload
+                .to_request(&echo_spawner)
+                .as_ref()
+                .map_err(crate::error::Err::kind),
+            Err(ErrKind::PayloadTooLarge { size, limit: 100 }) if *size == 4 * event_len
+        ));
+        Ok(())
     }
-}
 
-impl Ser for Response {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match *self {
-            Self::Success => serializer.serialize_str("success"),
-            Self::Failure => serializer.serialize_str("failure"),
-        }
+    #[test]
+    fn dedup_batches() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Error),
+            routed_event(EventType::Info),
+        ]);
+
+        assert_eq!(payload.prepare_events(&echo_spawner)?.len(), 3);
+
+        let _ = echo_spawner.set_dedup_batches(true);
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, EventType::Info);
+        assert_eq!(events[1].event_type, EventType::Error);
+
+        // Distinct events with colliding fingerprints are both kept
+        let mut events = payload.events.clone();
+        let mut origins = vec![0, 1, 2];
+        assert_eq!(remove_duplicates(&mut events, &mut origins, |_| 0), 1);
+        assert_eq!(events.len(), 2);
+        assert_eq!(origins, vec![0, 1]);
+        Ok(())
     }
-}
 
-impl<'de> Deser<'de> for Response {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        deserializer.deserialize_str(ResponseVisitor)
+    #[test]
+    fn rejected_indices_map_to_payload() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let (addr, _) = local_collector(&echo_spawner.handle(), false, |_, _| {
+            (
+                StatusCode::BAD_REQUEST,
+                r#"{"rejected":[{"index":1}]}"#.to_string(),
+            )
+        });
+        let _ = echo_spawner.set_dedup_batches(true);
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom(format!("http://{}/echo", addr)))
+            .set_events(vec![
+                routed_event(EventType::Info),
+                routed_event(EventType::Info),
+                routed_event(EventType::Error),
+            ]);
+
+        // The duplicate isn't sent, so the collector's index 1 is the payload's index 2
+        let sent = echo_spawner.send(&payload);
+        let rejection = match sent.as_ref().map_err(crate::error::Err::kind) {
+            Err(ErrKind::Rejected(rejection)) => rejection.clone(),
+            _ => return Err("expected a rejection".into()),
+        };
+        assert_eq!(*rejection.rejected()[0].index(), 2);
+        let subset = payload.rejected_subset(&rejection);
+        assert_eq!(subset.events.len(), 1);
+        assert_eq!(subset.events[0].event_type, EventType::Error);
+        Ok(())
     }
-}
 
-struct ResponseVisitor;
+    #[test]
+    fn fingerprint() {
+        let mut first = Event::default();
+        let mut second = Event::default();
+        let mut first_detail = HashMap::new();
+        let mut second_detail = HashMap::new();
 
-impl Visitor<'_> for ResponseVisitor {
-    type Value = Response;
+        for key in &["a", "b", "c", "d"] {
+            let _ = first_detail.insert((*key).to_string(), (*key).to_string());
+        }
+        for key in &["d", "c", "b", "a"] {
+            let _ = second_detail.insert((*key).to_string(), (*key).to_string());
+        }
+        let _ = first.set_message_detail(Some(first_detail));
+        let _ = second.set_message_detail(Some(second_detail));
+        assert_eq!(first.fingerprint(), second.fingerprint());
 
-    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("one of success or failure")
+        let _ = second.set_message("different");
+        assert_ne!(first.fingerprint(), second.fingerprint());
     }
 
-    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-    where
-        E: de::Error,
-    {
-        Ok(match value {
-            "success" => Response::Success,
-            "failure" => Response::Failure,
-            _ => return Err(E::custom(format!("invalid response: {}", value))),
-        })
+    #[test]
+    fn serialized_len() -> Result<()> {
+        let echo_event = routed_event(EventType::Info);
+        let event_len = serde_json::to_vec(&echo_event)?.len();
+        assert_eq!(echo_event.serialized_len()?, event_len);
+
+        let mut payload = Payload::default();
+        assert_eq!(payload.serialized_len()?, 2);
+        let _ = payload.set_events(vec![echo_event.clone(), echo_event]);
+        assert_eq!(payload.serialized_len()?, 2 * event_len + 3);
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use {
-        super::{Event, EventType, Payload, Response, Spawner},
-        crate::error::Result,
-        chrono::{offset::TimeZone, Utc},
-        slog::{o, Drain, Logger},
-        std::{collections::HashMap, sync::mpsc::channel, thread, time::Duration},
-        uuid::Uuid,
-    };
+    #[test]
+    fn semantic_eq() {
+        let mut first = routed_event(EventType::Info);
+        let _ = first
+            .set_message("testing")
+            .set_timestamp(Some(1))
+            .set_host(Some("first"))
+            .set_duration(Some(2));
+        let mut second = first.clone();
+        let _ = second
+            .set_timestamp(Some(3))
+            .set_host(Some("second"))
+            .set_duration(Some(4));
+        assert_ne!(first, second);
+        assert!(first.semantic_eq(&second));
+
+        let _ = second.set_correlation_id(Some(Uuid::new_v4()));
+        assert!(!first.semantic_eq(&second));
+    }
 
     #[test]
-    fn serialize_default() -> Result<()> {
-        let echo_event = Event::default();
-        let result = serde_json::to_string(&echo_event)?;
-        assert_eq!(result, r#"{"routingKey":"","type":"INFO","message":""}"#);
+    fn auto_timestamp() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let mut stamped = routed_event(EventType::Info);
+        let _ = stamped.set_timestamp(Some(1));
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            stamped,
+            routed_event(EventType::Error),
+        ]);
+
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert!(events[0].timestamp.is_some());
+        assert_eq!(events[1].timestamp(), Some(1));
+        assert_eq!(events[0].timestamp, events[2].timestamp);
+
+        let _ = echo_spawner.set_auto_timestamp(false);
+        let events = payload.prepare_events(&echo_spawner)?;
+        assert_eq!(events[0].timestamp(), None);
         Ok(())
     }
 
     #[test]
-    fn with_message() -> Result<()> {
-        let mut echo_event = Event::default();
-        let _ = echo_event.set_message("testing");
-        let result = serde_json::to_string(&echo_event)?;
-        assert_eq!(
-            result,
-            r#"{"routingKey":"","type":"INFO","message":"testing"}"#
-        );
+    fn default_host() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner
+            .set_auto_timestamp(false)
+            .set_default_host(Some("web-1".to_string()));
+        let mut hosted = routed_event(EventType::Info);
+        let _ = hosted.set_host(Some("web-2"));
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![routed_event(EventType::Info), hosted]);
+
+        let req = payload.to_request(&echo_spawner)?;
+        let expected = r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","host":"web-1"},{"routingKey":"atlas-dev-promises","type":"INFO","message":"","host":"web-2"}]"#;
+        assert_eq!(body_string(req)?, expected);
         Ok(())
     }
 
     #[test]
-    fn with_type() -> Result<()> {
-        let mut echo_event = Event::default();
-        let _ = echo_event.set_event_type(EventType::Performance);
-        let result = serde_json::to_string(&echo_event)?;
+    fn sequence_events() -> Result<()> {
+        let mut echo_spawner = Spawner::new()?;
+        without_stamping(&mut echo_spawner);
+        let _ = echo_spawner.set_sequence_events(true);
+
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![
+            routed_event(EventType::Info),
+            routed_event(EventType::Info),
+        ]);
+
+        let req = payload.to_request(&echo_spawner)?;
         assert_eq!(
-            result,
-            r#"{"routingKey":"","type":"PERFORMANCE","message":""}"#
+            body_string(req)?,
+            r#"[{"routingKey":"atlas-dev-promises","type":"INFO","message":"","sequence":0},{"routingKey":"atlas-dev-promises","type":"INFO","message":"","sequence":1}]"#
         );
         Ok(())
     }
 
+    #[cfg(feature = "protobuf")]
     #[test]
-    fn full() -> Result<()> {
-        let mut echo_event = Event::default();
-        let _ = echo_event.set_routing_key("atlas-dev-promises");
-        let _ = echo_event.set_event_type(EventType::System);
+    fn protobuf_round_trip() -> Result<()> {
+        use crate::proto;
+
+        let mut echo_event = routed_event(EventType::Performance);
         let _ = echo_event.set_message("testing");
         let _ = echo_event.set_correlation_id(Some(Uuid::parse_str(
             "35F3E1D6-D859-4AA0-8C58-2CDFE97A4710",
         )?));
-        let _ = echo_event.set_timestamp(Some(
-            Utc.ymd(1976, 3, 22)
-                .and_hms_milli(0, 0, 1, 666)
-                .timestamp_millis(),
-        ));
+        let _ = echo_event.set_timestamp(Some(196_300_801_666));
         let mut message_detail = HashMap::new();
-        let _ = message_detail.insert("a", "b");
-        let _ = echo_event.set_message_detail(Some(
-            message_detail
-                .iter_mut()
-                .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
-                .collect(),
-        ));
+        let _ = message_detail.insert("a".to_string(), "b".to_string());
+        let _ = echo_event.set_message_detail(Some(message_detail));
         let _ = echo_event.set_host(Some("host"));
-        let _ = echo_event.set_application_version(Some("1.2.3"));
-        let _ = echo_event.set_data_center(Some("cdc"));
-        let _ = echo_event.set_client_host_name(Some("blah"));
-        let _ = echo_event.set_destination_host_name(Some("blah1"));
-        let _ = echo_event.set_destination_path(Some("yoda"));
-        let _ = echo_event.set_start_timestamp(Some(1));
-        let _ = echo_event.set_finish_timestamp(Some(2));
-        let _ = echo_event.set_duration(Some(3));
         let _ = echo_event.set_duration_in_ms(Some(4));
         let _ = echo_event.set_response_code(Some(200));
         let _ = echo_event.set_response(Some(Response::Failure));
 
-        let result = serde_json::to_string(&echo_event)?;
-        assert_eq!(
-            result,
-            r#"{"routingKey":"atlas-dev-promises","type":"SYSTEM","message":"testing","correlationId":"35f3e1d6-d859-4aa0-8c58-2cdfe97a4710","timestamp":196300801666,"messageDetail":{"a":"b"},"host":"host","applicationVersion":"1.2.3","dataCenter":"cdc","clientHostName":"blah","destinationHostName":"blah1","destinationPath":"yoda","startTimestamp":1,"finishTimestamp":2,"duration":3,"durationInMs":4,"responseCode":200,"response":"failure"}"#
-        );
+        let mut echo_spawner = Spawner::new()?;
+        let _ = echo_spawner.set_protobuf(true);
+        let mut payload = Payload::default();
+        let _ = payload.set_events(vec![echo_event.clone(), Event::default()]);
+        let _ = echo_spawner.set_empty_routing_key(InvalidEventPolicy::Drop);
+
+        let req = payload.to_request(&echo_spawner)?;
+        assert_eq!(req.headers()["Content-Type"], "application/x-protobuf");
+
+        let bytes = block_on(hyper::body::to_bytes(req.into_body()))?;
+        let decoded = proto::decode(&bytes)?
+            .into_iter()
+            .map(Event::try_from)
+            .collect::<Result<Vec<Event>>>()?;
+        assert_eq!(decoded, vec![echo_event]);
         Ok(())
     }
 
@@ -583,6 +5614,31 @@ mod test {
         Logger::root(drain, o!())
     }
 
+    #[test]
+    fn logger_fallback() -> Result<()> {
+        let discard = || Logger::root(slog::Discard, o!());
+        let spawner_logger = discard().new(o!("spawner" => true));
+        let payload_logger = discard().new(o!("payload" => true));
+        let mut payload = Payload::default();
+
+        assert!(Spawner::new()?.logger_for(&payload).is_none());
+
+        let echo_spawner = Spawner::with_logger(spawner_logger.clone())?;
+        let resolved = echo_spawner.logger_for(&payload);
+        assert_eq!(
+            format!("{:?}", resolved),
+            format!("{:?}", Some(&spawner_logger))
+        );
+
+        let _ = payload.set_logger(Some(payload_logger.clone()));
+        let resolved = echo_spawner.logger_for(&payload);
+        assert_eq!(
+            format!("{:?}", resolved),
+            format!("{:?}", Some(&payload_logger))
+        );
+        Ok(())
+    }
+
     #[test]
     fn single_thread_spawn() -> Result<()> {
         let echo_spawner = Spawner::new()?;
@@ -600,6 +5656,7 @@ mod test {
 
         let mut payload = Payload::default();
         let _ = payload.set_logger(Some(logger));
+        let _ = payload.set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()));
         let _ = payload.set_events(vec![echo_event]);
 
         assert!(echo_spawner.spawn(&payload).is_ok());
@@ -638,6 +5695,7 @@ mod test {
             let _ = echo_event.set_message(format!("Message: {}", count));
             let mut payload = Payload::default();
             let _ = payload.set_logger(Some(logger.clone()));
+            let _ = payload.set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()));
             let _ = payload.set_events(vec![echo_event.clone()]);
             let _ = echo_spawner.spawn(&payload);
             count += 1;
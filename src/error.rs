@@ -21,24 +21,33 @@ pub struct Err {
     inner: ErrKind,
 }
 
-impl Error for Err {
-    fn description(&self) -> &str {
-        "libechoexec error"
+impl Err {
+    /// The kind of error, for branching on i.e. `ErrKind::Run` or
+    /// `ErrKind::Timeout`
+    #[must_use]
+    pub fn kind(&self) -> &ErrKind {
+        &self.inner
     }
+}
 
+impl Error for Err {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         Some(&self.inner)
     }
 }
 
+/// Formats as a single line chain, walking `source`, i.e.
+/// `libechoexec error: serde_json: EOF while parsing a value at line 1 column 0`
 impl fmt::Display for Err {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)?;
+        write!(f, "libechoexec error")?;
 
-        if let Some(source) = self.inner.source() {
-            write!(f, ": {}", source)?;
+        let mut source = self.source();
+        while let Some(cause) = source {
+            write!(f, ": {}", cause)?;
+            source = cause.source();
         }
-        write!(f, ": {}", self.inner)
+        Ok(())
     }
 }
 
@@ -60,6 +69,27 @@ impl From<ErrKind> for Err {
     }
 }
 
+impl From<Box<dyn Error + Send + Sync>> for Err {
+    fn from(inner: Box<dyn Error + Send + Sync>) -> Self {
+        let inner = match inner.downcast::<ErrKind>() {
+            Ok(kind) => return Self { inner: *kind },
+            Err(inner) => inner,
+        };
+        let inner = match inner.downcast::<Self>() {
+            Ok(err) => return *err,
+            Err(inner) => inner,
+        };
+        match inner.downcast::<hyper::Error>() {
+            Ok(err) => Self {
+                inner: ErrKind::Hyper(*err),
+            },
+            Err(inner) => Self {
+                inner: ErrKind::Str(inner.to_string()),
+            },
+        }
+    }
+}
+
 impl From<&str> for Err {
     fn from(inner: &str) -> Self {
         Self {
@@ -70,6 +100,7 @@ impl From<&str> for Err {
 
 external_error!(hyper::Error, ErrKind::Hyper);
 external_error!(hyper::http::Error, ErrKind::HyperHTTP);
+#[cfg(feature = "tls")]
 external_error!(native_tls::Error, ErrKind::NativeTLS);
 external_error!(serde_json::Error, ErrKind::SerdeJson);
 external_error!(std::io::Error, ErrKind::Io);
@@ -85,6 +116,7 @@ pub enum ErrKind {
     /// An HTTP error from the `hyper` library
     HyperHTTP(hyper::http::Error),
     /// An error from the `native_tls` library
+    #[cfg(feature = "tls")]
     NativeTLS(native_tls::Error),
     /// An Io error
     Io(std::io::Error),
@@ -96,32 +128,178 @@ pub enum ErrKind {
     Str(String),
     /// An env `VarError`
     Var(std::env::VarError),
-    /// Error during `Runnable` run
-    Run,
+    /// An event has both client and destination fields set
+    AmbiguousDirection,
+    /// A `response_code` or `response` field is set on an event type it doesn't apply to
+    MisplacedResponseField(crate::echo::EventType),
+    /// The event at the given batch index has an empty routing key
+    EmptyRoutingKey(usize),
+    /// The event at the given batch index has the reserved SYSTEM type, see
+    /// `Spawner::set_reject_system_events`
+    ReservedEventType(usize),
+    /// An error reading the bearer token file, or the file is empty.  The path is left
+    /// out, like the `TokenFile` `Debug` output, so it isn't leaked into logs.
+    TokenFile(std::io::Error),
+    /// The serialized payload exceeds the configured maximum size
+    PayloadTooLarge {
+        /// The serialized payload size in bytes
+        size: usize,
+        /// The maximum payload size in bytes
+        limit: usize,
+    },
+    /// The routing key contains disallowed characters or isn't of the form
+    /// `<application group>-<application name>-<environment>`
+    InvalidRoutingKey(String),
+    /// The collector didn't respond within the `Spawner` timeout
+    Timeout,
+    /// Error during `Runnable` run, the collector responded with an error status
+    Run {
+        /// The HTTP status code
+        status: u16,
+        /// The response body, possibly truncated if reading it timed out
+        body: String,
+        /// The delay asked for by the `Retry-After` header of a `429` or `503` response,
+        /// honored instead of the retry backoff
+        retry_after: Option<std::time::Duration>,
+    },
+    /// The collector rejected some of the events in the batch
+    Rejected(crate::ack::BatchRejection),
+    /// A background worker, i.e. of a `BatchingSpawner` or `Spawner::sink`, has stopped
+    WorkerStopped,
+    /// The `BatchingSpawner` worker kept panicking, and was stopped after its restart
+    /// limit
+    WorkerDead,
+    /// The `Spawner` has been shut down and no longer accepts sends
+    ShutDown,
+    /// The send exceeds the `SpawnerConfig` `max_requests_per_sec` rate
+    RateLimited,
+    /// The collector circuit breaker is open, see `SpawnerConfig::set_circuit_threshold`
+    CircuitOpen,
+    /// The problems found validating a payload, each prefixed with the event index
+    Validation(Vec<String>),
+    /// The string isn't a recognized event type
+    ParseEventType(String),
+    /// The string isn't a recognized response
+    ParseResponse(String),
+    /// An event was marked finished without being marked started, or before the time
+    /// it was started
+    FinishBeforeStart {
+        /// The start timestamp in millis, if any
+        start: Option<u64>,
+        /// The finish timestamp in millis
+        finish: u64,
+    },
 }
 
 impl Error for ErrKind {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::Hyper(inner) => inner.source(),
-            Self::HyperHTTP(inner) => inner.source(),
-            Self::NativeTLS(inner) => inner.source(),
-            Self::Io(inner) => inner.source(),
-            Self::ParseUuid(inner) => inner.source(),
-            Self::SerdeJson(inner) => inner.source(),
-            Self::Var(inner) => inner.source(),
+            Self::Hyper(inner) => Some(inner),
+            Self::HyperHTTP(inner) => Some(inner),
+            #[cfg(feature = "tls")]
+            Self::NativeTLS(inner) => Some(inner),
+            Self::Io(inner) | Self::TokenFile(inner) => Some(inner),
+            Self::ParseUuid(inner) => Some(inner),
+            Self::SerdeJson(inner) => Some(inner),
+            Self::Var(inner) => Some(inner),
             _ => None,
         }
     }
 }
 
+/// Each kind displays only its own level, the wrapped error, if any, is its `source`
 impl fmt::Display for ErrKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self)?;
         match self {
-            Self::Io(inner) => write!(f, ": {}", inner),
-            Self::Var(inner) => write!(f, ": {}", inner),
-            _ => write!(f, ""),
+            Self::Hyper(_) => write!(f, "hyper"),
+            Self::HyperHTTP(_) => write!(f, "http"),
+            #[cfg(feature = "tls")]
+            Self::NativeTLS(_) => write!(f, "native_tls"),
+            Self::Io(_) => write!(f, "io"),
+            Self::ParseUuid(_) => write!(f, "uuid"),
+            Self::SerdeJson(_) => write!(f, "serde_json"),
+            Self::Str(inner) => write!(f, "{}", inner),
+            Self::Var(_) => write!(f, "env"),
+            Self::AmbiguousDirection => {
+                write!(f, "event has both client and destination fields set")
+            }
+            Self::MisplacedResponseField(event_type) => write!(
+                f,
+                "response fields don't apply to {} events",
+                event_type.as_str()
+            ),
+            Self::EmptyRoutingKey(idx) => write!(f, "event {} has an empty routing key", idx),
+            Self::ReservedEventType(idx) => {
+                write!(f, "event {} has the reserved SYSTEM event type", idx)
+            }
+            Self::TokenFile(_) => write!(f, "token file"),
+            Self::PayloadTooLarge { size, limit } => write!(
+                f,
+                "payload too large: {} bytes exceeds the {} byte limit",
+                size, limit
+            ),
+            Self::InvalidRoutingKey(key) => write!(f, "invalid routing key {:?}", key),
+            Self::Timeout => write!(f, "timed out waiting for the collector"),
+            Self::Run { status, body, .. } => {
+                write!(f, "error response: {} {}", status, body)
+            }
+            Self::Rejected(rejection) => {
+                write!(f, "{} events rejected", rejection.rejected().len())
+            }
+            Self::WorkerStopped => write!(f, "background worker stopped"),
+            Self::WorkerDead => write!(f, "background worker stopped after repeated panics"),
+            Self::ShutDown => write!(f, "spawner shut down"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::CircuitOpen => write!(f, "collector circuit open"),
+            Self::Validation(problems) => write!(f, "invalid payload: {}", problems.join("; ")),
+            Self::ParseEventType(value) => write!(f, "unknown event type {:?}", value),
+            Self::ParseResponse(value) => write!(f, "unknown response {:?}", value),
+            Self::FinishBeforeStart {
+                start: Some(start),
+                finish,
+            } => write!(
+                f,
+                "finish timestamp {} precedes start timestamp {}",
+                finish, start
+            ),
+            Self::FinishBeforeStart { start: None, .. } => {
+                write!(f, "event finished without being started")
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{Err, ErrKind},
+        std::error::Error,
+    };
+
+    #[test]
+    fn display_chain() {
+        let err = Err::from(serde_json::from_str::<u32>("").expect_err("EOF"));
+        assert_eq!(
+            format!("{}", err),
+            "libechoexec error: serde_json: EOF while parsing a value at line 1 column 0"
+        );
+        assert!(matches!(err.kind(), ErrKind::SerdeJson(_)));
+
+        let kind = err.source().expect("kind");
+        assert_eq!(format!("{}", kind), "serde_json");
+        assert!(kind.downcast_ref::<ErrKind>().is_some());
+        let cause = kind.source().expect("serde error");
+        assert_eq!(
+            format!("{}", cause),
+            "EOF while parsing a value at line 1 column 0"
+        );
+        assert!(cause.source().is_none());
+
+        let err = Err::from(ErrKind::PayloadTooLarge { size: 2, limit: 1 });
+        assert_eq!(
+            format!("{}", err),
+            "libechoexec error: payload too large: 2 bytes exceeds the 1 byte limit"
+        );
+        assert!(err.source().and_then(Error::source).is_none());
+    }
+}
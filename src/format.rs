@@ -0,0 +1,26 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! JSON payload framing
+
+use serde_derive::{Deserialize, Serialize};
+
+/// The framing of the JSON events in a request body
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PayloadFormat {
+    /// A JSON array of events, `application/json` (the Echo collector default)
+    JsonArray,
+    /// One JSON event per line, `application/x-ndjson`
+    Ndjson,
+}
+
+impl Default for PayloadFormat {
+    fn default() -> Self {
+        Self::JsonArray
+    }
+}
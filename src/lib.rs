@@ -101,6 +101,20 @@
 //! #     Ok(())
 //! # }
 //! ```
+//!
+//! # Features
+//!
+//! * `tls` (default) - TLS with `native-tls` (OpenSSL on Linux).  This can't negotiate
+//!   HTTP/2 via ALPN, so it only speaks HTTP/2 with
+//!   `SpawnerConfig::set_http2_prior_knowledge` or `SpawnerConfig::set_http2_only`, see
+//!   there.
+//! * `rustls` - TLS with `rustls`, for fully static builds, i.e. on musl.  This is
+//!   mutually exclusive with `tls`, so disable the default features to use it.
+//!   `Spawner::with_identity` is only available with `tls`, use
+//!   `Spawner::with_pem_identity` for mutual TLS with either backend.
+//! * `protobuf` - send events protobuf encoded, see `Spawner::set_protobuf`
+//! * `test-util` - `MockSpawner`, an `EchoSink` that records events in memory rather
+//!   than sending them, for asserting on events in downstream tests
 #![feature(crate_visibility_modifier)]
 #![deny(
     absolute_paths_not_starting_with_crate,
@@ -132,10 +146,71 @@
 #![allow(box_pointers)]
 #![doc(html_root_url = "https://docs.rs/echoloc/0.1.0")]
 
+#[cfg(all(feature = "tls", feature = "rustls"))]
+compile_error!("the `tls` and `rustls` features are mutually exclusive");
+#[cfg(not(any(feature = "tls", feature = "rustls")))]
+compile_error!("one of the `tls` or `rustls` features is required");
+
+mod ack;
+mod auth;
+mod backoff;
+mod batch;
+mod body;
+mod builder;
+mod casing;
+mod circuit;
+mod config;
+mod dns;
+mod drain;
 mod echo;
 mod error;
+mod format;
+mod limit;
+mod logging;
+mod metadata;
+mod metrics;
+mod openmetrics;
+mod operation;
+mod pause;
+#[cfg(feature = "protobuf")]
+mod proto;
+mod sampling;
+mod sink;
+mod stats;
+mod stream;
+mod timestamp;
+mod tls;
+mod validate;
+mod wrapper;
 
 pub use {
-    echo::{CollectorUrl, Event, EventType, Payload, Response, Spawner},
+    ack::{BatchAck, BatchRejection, CollectorAck, RejectedEvent},
+    auth::TokenFile,
+    backoff::{Jitter, ThreadRngJitter},
+    batch::BatchingSpawner,
+    builder::EventBuilder,
+    casing::FieldCasing,
+    circuit::CircuitState,
+    config::SpawnerConfig,
+    echo::{
+        CollectorUrl, CustomEventType, Event, EventType, Payload, PersistablePayload, Response,
+        Spawner,
+    },
     error::{Err, ErrKind, Result},
+    format::PayloadFormat,
+    limit::RateLimitPolicy,
+    logging::{LogLevels, Outcome},
+    metadata::{AwsRegion, DataCenterResolver},
+    metrics::{Metrics, NoopMetrics},
+    operation::Operation,
+    pause::PausePolicy,
+    sampling::SamplingPolicy,
+    sink::EchoSink,
+    stats::{BytesSent, ShutdownSummary},
+    stream::StreamConfig,
+    validate::{InvalidEventPolicy, Strictness},
+    wrapper::RootWrapper,
 };
+
+#[cfg(any(test, feature = "test-util"))]
+pub use sink::MockSpawner;
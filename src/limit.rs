@@ -0,0 +1,357 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Request limiting
+
+use {
+    std::{
+        collections::HashMap,
+        convert::TryFrom,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    tokio::{
+        sync::{Mutex as AsyncMutex, Semaphore, SemaphorePermit},
+        time::delay_for,
+    },
+};
+
+/// The number of bytes each `ByteBudget` permit represents
+const BUDGET_UNIT: usize = 1024;
+
+/// Caps the number of concurrent requests to each collector host independently, so
+/// one slow host can't starve requests to healthy hosts.
+#[derive(Debug, Default)]
+pub(crate) struct HostLimits {
+    /// The maximum concurrent requests per host, `None` is unlimited
+    limit: Option<usize>,
+    /// A semaphore per host, created on first use
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    /// Create a new per-host limiter.  A limit of 0 is raised to 1, rather than
+    /// blocking every request.
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit: limit.map(|limit| limit.max(1)),
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the semaphore for the given host, if requests are limited
+    pub(crate) fn semaphore(&self, host: &str) -> Option<Arc<Semaphore>> {
+        let limit = self.limit?;
+        let mut semaphores = self.semaphores.lock().ok()?;
+
+        Some(
+            semaphores
+                .entry(host.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+                .clone(),
+        )
+    }
+}
+
+/// Caps the total size of the request bodies in flight at once, so many large bodies
+/// sent concurrently can't exhaust memory.  Each send holds permits proportional to its
+/// body size, in `BUDGET_UNIT` byte units, until it completes.
+#[derive(Debug)]
+pub(crate) struct ByteBudget {
+    /// The budget permits
+    permits: Semaphore,
+    /// The total number of permits
+    total: usize,
+    /// Serializes acquisition, so two sends can't each hold part of the budget while
+    /// waiting on the other
+    acquiring: AsyncMutex<()>,
+}
+
+impl ByteBudget {
+    /// Create a new budget of `max_bytes` in flight bytes
+    pub(crate) fn new(max_bytes: usize) -> Self {
+        let total = units(max_bytes).max(1);
+
+        Self {
+            permits: Semaphore::new(total),
+            total,
+            acquiring: AsyncMutex::new(()),
+        }
+    }
+
+    /// Wait for budget for a body of `bytes` bytes.  The budget is returned when the
+    /// permits are dropped.  A body larger than the whole budget waits for the whole
+    /// budget.
+    pub(crate) async fn acquire(&self, bytes: usize) -> Vec<SemaphorePermit<'_>> {
+        let needed = units(bytes).max(1).min(self.total);
+        let _acquiring = self.acquiring.lock().await;
+        let mut permits = Vec::with_capacity(needed);
+
+        for _ in 0..needed {
+            permits.push(self.permits.acquire().await);
+        }
+        permits
+    }
+
+    /// The bytes of budget currently available
+    #[cfg(test)]
+    pub(crate) fn available_bytes(&self) -> usize {
+        self.permits.available_permits() * BUDGET_UNIT
+    }
+}
+
+/// The limits a send waits on before it's sent, i.e. the request rate, the in-flight
+/// byte budget, and the concurrent requests to its collector host
+#[derive(Debug, Default)]
+pub(crate) struct SendLimits {
+    /// The request rate limit, if any
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    /// The in-flight byte budget, if any
+    pub(crate) byte_budget: Option<Arc<ByteBudget>>,
+    /// The concurrent request limit of the collector host, if any
+    pub(crate) host_limit: Option<Arc<Semaphore>>,
+}
+
+/// The permits a send holds until it completes, released when dropped
+#[derive(Debug)]
+pub(crate) struct SendPermits<'a> {
+    /// The byte budget permits
+    _budget: Vec<SemaphorePermit<'a>>,
+    /// The host limit permit
+    _host: Option<SemaphorePermit<'a>>,
+}
+
+impl SendLimits {
+    /// Wait for the rate limit, then for the host limit, then for budget for a body of
+    /// `bytes` bytes.  The host limit comes first, so sends queued on a slow host don't
+    /// hold budget needed by sends to healthy hosts.
+    pub(crate) async fn acquire(&self, bytes: usize) -> SendPermits<'_> {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire().await;
+        }
+        let host = match &self.host_limit {
+            Some(semaphore) => Some(semaphore.acquire().await),
+            None => None,
+        };
+        let budget = match &self.byte_budget {
+            Some(byte_budget) => byte_budget.acquire(bytes).await,
+            None => vec![],
+        };
+        SendPermits {
+            _budget: budget,
+            _host: host,
+        }
+    }
+}
+
+/// What to do with sends over the `SpawnerConfig` `max_requests_per_sec` rate
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitPolicy {
+    /// Wait for the rate to allow the send
+    Delay,
+    /// Fail the send with `ErrKind::RateLimited`
+    Reject,
+}
+
+impl Default for RateLimitPolicy {
+    fn default() -> Self {
+        Self::Delay
+    }
+}
+
+/// Limits requests to a steady rate per second, allowing bursts of up to a second's
+/// worth of requests (a token bucket, implemented as a generic cell rate algorithm)
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// The time between requests at the steady rate
+    interval: Duration,
+    /// How far ahead of the steady rate a burst may run
+    burst: Duration,
+    /// When the next request would be sent at the steady rate
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a new limiter allowing `per_sec` requests per second
+    pub(crate) fn new(per_sec: u32) -> Self {
+        let interval = Duration::from_secs(1) / per_sec.max(1);
+
+        Self {
+            interval,
+            burst: Duration::from_secs(1) - interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take a token now, or return how long until one is available
+    fn take(&self) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        match self.next.lock() {
+            Ok(mut next) => {
+                let scheduled = (*next).max(now);
+                let ahead = scheduled - now;
+
+                if ahead > self.burst {
+                    Err(ahead - self.burst)
+                } else {
+                    *next = scheduled + self.interval;
+                    Ok(())
+                }
+            }
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Take a token if one is available now
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    /// Wait for a token
+    pub(crate) async fn acquire(&self) {
+        while let Err(wait) = self.take() {
+            delay_for(wait).await;
+        }
+    }
+
+    /// The number of tokens available now
+    pub(crate) fn available(&self) -> u32 {
+        let ahead = self.next.lock().map_or(Duration::default(), |next| {
+            next.saturating_duration_since(Instant::now())
+        });
+        let slack = (self.burst + self.interval)
+            .checked_sub(ahead)
+            .unwrap_or_default();
+        u32::try_from(slack.as_nanos() / self.interval.as_nanos()).unwrap_or(u32::max_value())
+    }
+}
+
+/// The number of budget units covering `bytes`
+fn units(bytes: usize) -> usize {
+    (bytes + BUDGET_UNIT - 1) / BUDGET_UNIT
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{ByteBudget, HostLimits, RateLimiter, SendLimits},
+        std::{sync::Arc, time::Duration},
+        tokio::{runtime::Runtime, time::timeout},
+    };
+
+    #[test]
+    fn byte_budget() -> crate::error::Result<()> {
+        let mut rt = Runtime::new()?;
+        let budget = ByteBudget::new(4096);
+
+        rt.block_on(async {
+            let first = budget.acquire(3000).await;
+            assert_eq!(first.len(), 3);
+            assert_eq!(budget.available_bytes(), 1024);
+
+            // Waits until the first body completes
+            assert!(timeout(Duration::from_millis(50), budget.acquire(2000))
+                .await
+                .is_err());
+            drop(first);
+            assert_eq!(budget.acquire(2000).await.len(), 2);
+
+            // Oversized bodies wait for the whole budget, rather than forever
+            assert_eq!(budget.acquire(10_000).await.len(), 4);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn send_limits() -> crate::error::Result<()> {
+        let mut rt = Runtime::new()?;
+        let limits = SendLimits {
+            rate_limiter: None,
+            byte_budget: Some(Arc::new(ByteBudget::new(2048))),
+            host_limit: HostLimits::new(Some(1)).semaphore("a"),
+        };
+
+        rt.block_on(async {
+            let permits = limits.acquire(1024).await;
+            assert!(timeout(Duration::from_millis(50), limits.acquire(1))
+                .await
+                .is_err());
+            drop(permits);
+            let _permits = limits.acquire(2048).await;
+            assert_eq!(
+                limits
+                    .byte_budget
+                    .as_ref()
+                    .map(|budget| budget.available_bytes()),
+                Some(0)
+            );
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn host_limit_before_budget() -> crate::error::Result<()> {
+        let mut rt = Runtime::new()?;
+        let budget = Arc::new(ByteBudget::new(2048));
+        let hosts = HostLimits::new(Some(1));
+        let slow = SendLimits {
+            rate_limiter: None,
+            byte_budget: Some(budget.clone()),
+            host_limit: hosts.semaphore("slow"),
+        };
+        let healthy = SendLimits {
+            rate_limiter: None,
+            byte_budget: Some(budget.clone()),
+            host_limit: hosts.semaphore("healthy"),
+        };
+
+        rt.block_on(async {
+            let _in_flight = slow.acquire(1024).await;
+            let mut queued = Box::pin(slow.acquire(1024));
+            assert!(futures::poll!(&mut queued).is_pending());
+            assert_eq!(budget.available_bytes(), 1024);
+            assert!(timeout(Duration::from_millis(50), healthy.acquire(1024))
+                .await
+                .is_ok());
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn rate_limiter() -> crate::error::Result<()> {
+        let limiter = RateLimiter::new(2);
+        assert_eq!(limiter.available(), 2);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        assert_eq!(limiter.available(), 0);
+
+        // Waits for the next token, rather than failing
+        let mut rt = Runtime::new()?;
+        rt.block_on(timeout(Duration::from_secs(1), limiter.acquire()))
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn per_host() {
+        assert!(HostLimits::new(None).semaphore("a").is_none());
+
+        let limits = HostLimits::new(Some(2));
+        let a = limits.semaphore("a").expect("semaphore");
+        let b = limits.semaphore("b").expect("semaphore");
+        assert!(!Arc::ptr_eq(&a, &b));
+        assert!(Arc::ptr_eq(&a, &limits.semaphore("a").expect("semaphore")));
+        assert_eq!(a.available_permits(), 2);
+
+        let limits = HostLimits::new(Some(0));
+        let a = limits.semaphore("a").expect("semaphore");
+        assert_eq!(a.available_permits(), 1);
+    }
+}
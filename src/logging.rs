@@ -0,0 +1,124 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Outcome based `slog` levels
+
+use {
+    getset::Setters,
+    slog::{crit, debug, error, info, trace, warn, Level, Logger},
+    std::fmt,
+};
+
+/// The outcome of sending an Echo Payload
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    /// The payload was accepted by the collector
+    Success,
+    /// The collector returned a 4xx status
+    ClientError,
+    /// The collector returned a 5xx status
+    ServerError,
+    /// The collector returned a status that is neither a success nor an error, i.e. a
+    /// 1xx or 3xx status
+    UnexpectedStatus,
+    /// The payload is being retried
+    Retry,
+    /// The payload has been dropped
+    Drop,
+}
+
+/// The `slog` level used to log each send outcome.  A level of `None` silences that
+/// outcome.
+///
+/// The default logs successes at `Trace`, and errors and retries at `Error`.  Dropped
+/// payloads aren't logged by default, the error that dropped them already is.
+#[derive(Clone, Copy, Debug, PartialEq, Setters)]
+pub struct LogLevels {
+    /// The level for successful sends
+    #[set = "pub"]
+    success: Option<Level>,
+    /// The level for client (4xx) errors
+    #[set = "pub"]
+    client_error: Option<Level>,
+    /// The level for server (5xx) errors
+    #[set = "pub"]
+    server_error: Option<Level>,
+    /// The level for unexpected (1xx or 3xx) statuses
+    #[set = "pub"]
+    unexpected_status: Option<Level>,
+    /// The level for retries
+    #[set = "pub"]
+    retry: Option<Level>,
+    /// The level for dropped payloads
+    #[set = "pub"]
+    drop: Option<Level>,
+}
+
+impl Default for LogLevels {
+    fn default() -> Self {
+        Self {
+            success: Some(Level::Trace),
+            client_error: Some(Level::Error),
+            server_error: Some(Level::Error),
+            unexpected_status: Some(Level::Error),
+            retry: Some(Level::Error),
+            drop: None,
+        }
+    }
+}
+
+impl LogLevels {
+    /// The level configured for the given outcome
+    #[must_use]
+    pub fn level(self, outcome: Outcome) -> Option<Level> {
+        match outcome {
+            Outcome::Success => self.success,
+            Outcome::ClientError => self.client_error,
+            Outcome::ServerError => self.server_error,
+            Outcome::UnexpectedStatus => self.unexpected_status,
+            Outcome::Retry => self.retry,
+            Outcome::Drop => self.drop,
+        }
+    }
+
+    /// Log the message at the level configured for the given outcome
+    pub(crate) fn log(self, logger: &Option<Logger>, outcome: Outcome, args: fmt::Arguments<'_>) {
+        if let (Some(logger), Some(level)) = (logger, self.level(outcome)) {
+            // The `slog` macros need the level at compile time
+            match level {
+                Level::Critical => crit!(logger, "{}", args),
+                Level::Error => error!(logger, "{}", args),
+                Level::Warning => warn!(logger, "{}", args),
+                Level::Info => info!(logger, "{}", args),
+                Level::Debug => debug!(logger, "{}", args),
+                Level::Trace => trace!(logger, "{}", args),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{LogLevels, Outcome},
+        slog::Level,
+    };
+
+    #[test]
+    fn levels() {
+        let mut levels = LogLevels::default();
+        assert_eq!(levels.level(Outcome::Success), Some(Level::Trace));
+        assert_eq!(levels.level(Outcome::ServerError), Some(Level::Error));
+        assert_eq!(levels.level(Outcome::UnexpectedStatus), Some(Level::Error));
+        assert_eq!(levels.level(Outcome::Drop), None);
+
+        let _ = levels.set_success(None).set_retry(Some(Level::Warning));
+        assert_eq!(levels.level(Outcome::Success), None);
+        assert_eq!(levels.level(Outcome::Retry), Some(Level::Warning));
+    }
+}
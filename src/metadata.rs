@@ -0,0 +1,165 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Cloud metadata data center resolution
+
+use {
+    crate::tls::HttpsClient,
+    hyper::{Body, Request},
+    std::{error::Error, fmt, time::Duration},
+    tokio::time::timeout,
+};
+
+/// How long to wait on the metadata service before falling back
+const METADATA_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The header carrying an EC2 instance metadata service (IMDSv2) session token
+const AWS_TOKEN: &str = "X-aws-ec2-metadata-token";
+
+/// The header requesting an IMDSv2 session token lifetime, in seconds
+const AWS_TOKEN_TTL: &str = "X-aws-ec2-metadata-token-ttl-seconds";
+
+/// Resolve the data center an application is running in from a cloud metadata service.
+///
+/// Implement this for clouds other than the ones supported out of the box.
+pub trait DataCenterResolver: fmt::Debug + Send + Sync {
+    /// Build the request for a session token, sent ahead of the metadata request, if
+    /// the metadata service issues them.  If the token can't be fetched, the metadata
+    /// request is sent without one.
+    fn token_request(&self) -> Option<crate::error::Result<Request<Body>>> {
+        None
+    }
+
+    /// Build the request sent to the metadata service, with the session token, if one
+    /// was fetched
+    ///
+    /// # Errors
+    ///
+    fn request(&self, token: Option<&str>) -> crate::error::Result<Request<Body>>;
+
+    /// Extract the data center from the metadata response body
+    fn parse(&self, body: &str) -> Option<String>;
+}
+
+/// Resolves the AWS region from the EC2 instance metadata service.  An IMDSv2 session
+/// token is fetched first, as instances may require one, falling back to IMDSv1.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AwsRegion;
+
+impl DataCenterResolver for AwsRegion {
+    fn token_request(&self) -> Option<crate::error::Result<Request<Body>>> {
+        Some(
+            Request::builder()
+                .method("PUT")
+                .uri("http://169.254.169.254/latest/api/token")
+                .header(AWS_TOKEN_TTL, "60")
+                .body(Body::empty())
+                .map_err(Into::into),
+        )
+    }
+
+    fn request(&self, token: Option<&str>) -> crate::error::Result<Request<Body>> {
+        let mut builder = Request::builder()
+            .method("GET")
+            .uri("http://169.254.169.254/latest/meta-data/placement/region");
+
+        if let Some(token) = token {
+            builder = builder.header(AWS_TOKEN, token);
+        }
+        Ok(builder.body(Body::empty())?)
+    }
+
+    fn parse(&self, body: &str) -> Option<String> {
+        let region = body.trim();
+
+        if region.is_empty() {
+            None
+        } else {
+            Some(region.to_string())
+        }
+    }
+}
+
+/// Query the metadata service via the given resolver, returning `None` on any failure
+/// or if the service doesn't respond within a short timeout.
+pub(crate) async fn resolve(
+    client: HttpsClient,
+    resolver: &dyn DataCenterResolver,
+) -> Option<String> {
+    match timeout(METADATA_TIMEOUT, fetch(client, resolver)).await {
+        Ok(Ok(data_center)) => data_center,
+        _ => None,
+    }
+}
+
+async fn fetch(
+    client: HttpsClient,
+    resolver: &dyn DataCenterResolver,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let token = match resolver.token_request() {
+        Some(Ok(req)) => session_token(&client, req).await,
+        _ => None,
+    };
+    let resp = client.request(resolver.request(token.as_deref())?).await?;
+
+    if resp.status().is_success() {
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        Ok(resolver.parse(&String::from_utf8_lossy(&body)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Fetch a metadata session token, `None` if the service doesn't issue one
+async fn session_token(client: &HttpsClient, req: Request<Body>) -> Option<String> {
+    let resp = client.request(req).await.ok()?;
+
+    if !resp.status().is_success() {
+        return None;
+    }
+    let body = hyper::body::to_bytes(resp.into_body()).await.ok()?;
+    let token = String::from_utf8_lossy(&body).trim().to_string();
+
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{AwsRegion, DataCenterResolver, AWS_TOKEN, AWS_TOKEN_TTL},
+        crate::error::Result,
+    };
+
+    #[test]
+    fn aws_requests() -> Result<()> {
+        let token_req = AwsRegion.token_request().expect("token request")?;
+        assert_eq!(token_req.method(), "PUT");
+        assert_eq!(token_req.uri().path(), "/latest/api/token");
+        assert!(token_req.headers().contains_key(AWS_TOKEN_TTL));
+
+        let req = AwsRegion.request(Some("token"))?;
+        assert_eq!(req.headers()[AWS_TOKEN], "token");
+
+        // IMDSv1, without a token
+        assert!(!AwsRegion.request(None)?.headers().contains_key(AWS_TOKEN));
+        Ok(())
+    }
+
+    #[test]
+    fn aws_region() {
+        assert_eq!(
+            AwsRegion.parse("us-east-1\n"),
+            Some("us-east-1".to_string())
+        );
+        assert_eq!(AwsRegion.parse("  "), None);
+    }
+}
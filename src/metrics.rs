@@ -0,0 +1,33 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Send metrics
+
+use std::{fmt, time::Duration};
+
+/// A sink for per-request send metrics, i.e. to bridge into Prometheus or StatsD.
+///
+/// The `Spawner` uses `NoopMetrics` by default.  Each request sent to the collector,
+/// including each retry, reports exactly one success or failure.
+pub trait Metrics: fmt::Debug + Send + Sync {
+    /// A request succeeded, taking `latency` to respond, with a body of `bytes` bytes
+    fn on_success(&self, latency: Duration, bytes: usize);
+
+    /// A request failed, with the HTTP `status` if the collector responded
+    fn on_failure(&self, status: Option<u16>);
+}
+
+/// Metrics that are discarded
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {
+    fn on_success(&self, _latency: Duration, _bytes: usize) {}
+
+    fn on_failure(&self, _status: Option<u16>) {}
+}
@@ -0,0 +1,131 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! OpenMetrics exposition of event counts
+
+use {
+    crate::stats::Stats,
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        sync::{atomic::Ordering, Mutex},
+    },
+};
+
+/// Accumulated counts of submitted events, keyed by event type and routing key
+#[derive(Debug, Default)]
+pub(crate) struct EventCounts {
+    counts: Mutex<BTreeMap<(String, String), u64>>,
+}
+
+impl EventCounts {
+    /// Record a batch of submitted events as `(event type, routing key)` pairs
+    pub(crate) fn record<'a, I>(&self, events: I)
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        if let Ok(mut counts) = self.counts.lock() {
+            for (event_type, routing_key) in events {
+                *counts
+                    .entry((event_type.to_string(), routing_key.to_string()))
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Render the counts, plus the filtered and paused counts, in OpenMetrics text format
+    pub(crate) fn render(&self, stats: &Stats) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "# TYPE echo_events counter");
+        let _ = writeln!(
+            text,
+            "# HELP echo_events Echo events submitted by type and routing key"
+        );
+
+        if let Ok(counts) = self.counts.lock() {
+            for ((event_type, routing_key), count) in counts.iter() {
+                let _ = writeln!(
+                    text,
+                    "echo_events_total{{type=\"{}\",routing_key=\"{}\"}} {}",
+                    escape(event_type),
+                    escape(routing_key),
+                    count
+                );
+            }
+        }
+
+        let _ = writeln!(text, "# TYPE echo_events_filtered counter");
+        let _ = writeln!(
+            text,
+            "# HELP echo_events_filtered Echo events dropped by the severity filter"
+        );
+        let _ = writeln!(
+            text,
+            "echo_events_filtered_total {}",
+            stats.filtered.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(text, "# TYPE echo_events_paused counter");
+        let _ = writeln!(
+            text,
+            "# HELP echo_events_paused Echo events spawned while paused by action"
+        );
+        let _ = writeln!(
+            text,
+            "echo_events_paused_total{{action=\"buffered\"}} {}",
+            stats.paused_buffered.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            text,
+            "echo_events_paused_total{{action=\"dropped\"}} {}",
+            stats.paused_dropped.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(text, "# EOF");
+        text
+    }
+}
+
+/// Escape an OpenMetrics label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use {super::EventCounts, crate::stats::Stats};
+
+    #[test]
+    fn render() {
+        let counts = EventCounts::default();
+        counts.record(vec![("INFO", "atlas-dev-promises"), ("ERROR", "a\"b")]);
+        counts.record(vec![("INFO", "atlas-dev-promises")]);
+
+        let stats = Stats::default();
+        Stats::add(&stats.filtered, 2);
+        Stats::add(&stats.paused_dropped, 3);
+
+        assert_eq!(
+            counts.render(&stats),
+            r#"# TYPE echo_events counter
+# HELP echo_events Echo events submitted by type and routing key
+echo_events_total{type="ERROR",routing_key="a\"b"} 1
+echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 2
+# TYPE echo_events_filtered counter
+# HELP echo_events_filtered Echo events dropped by the severity filter
+echo_events_filtered_total 2
+# TYPE echo_events_paused counter
+# HELP echo_events_paused Echo events spawned while paused by action
+echo_events_paused_total{action="buffered"} 0
+echo_events_paused_total{action="dropped"} 3
+# EOF
+"#
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Correlated start and finish events
+
+use {
+    crate::echo::Event,
+    chrono::{DateTime, Utc},
+    std::convert::TryFrom,
+    uuid::Uuid,
+};
+
+/// An in-progress operation, used to build a start event and a later finish event that
+/// are guaranteed to share a correlation id and consistent timestamps.
+///
+/// ```
+/// # use libechoexec::{Event, EventType, Operation};
+/// let mut template = Event::default();
+/// let _ = template
+///     .set_routing_key("atlas-dev-promises")
+///     .set_event_type(EventType::Performance);
+///
+/// let operation = Operation::begin(&template);
+/// let mut start = operation.start_event();
+/// let _ = start.set_message("Fetching promises");
+/// // ... perform the operation ...
+/// let mut finish = operation.finish_event();
+/// let _ = finish.set_message("Fetched promises");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Operation {
+    /// The event both the start and finish events are built from
+    template: Event,
+    /// The correlation id shared by the start and finish events
+    correlation_id: Uuid,
+    /// When the operation started
+    started: DateTime<Utc>,
+}
+
+impl Operation {
+    /// Begin an operation now.  The start and finish events are copies of `template`,
+    /// with a newly generated correlation id.
+    #[must_use]
+    pub fn begin(template: &Event) -> Self {
+        Self {
+            template: template.clone(),
+            correlation_id: Uuid::new_v4(),
+            started: Utc::now(),
+        }
+    }
+
+    /// The correlation id shared by the start and finish events
+    #[must_use]
+    pub fn correlation_id(&self) -> Uuid {
+        self.correlation_id
+    }
+
+    /// The start event, timestamped when the operation began
+    #[must_use]
+    pub fn start_event(&self) -> Event {
+        let mut event = self.template.clone();
+        let _ = event
+            .set_correlation_id(Some(self.correlation_id))
+            .set_timestamp_dt(self.started)
+            .set_start_timestamp_dt(self.started);
+        event
+    }
+
+    /// The finish event, timestamped now, carrying the start and finish timestamps and
+    /// the operation duration
+    #[must_use]
+    pub fn finish_event(&self) -> Event {
+        let finished = Utc::now().max(self.started);
+        let duration_in_ms = u64::try_from((finished - self.started).num_milliseconds()).ok();
+
+        let mut event = self.template.clone();
+        let _ = event
+            .set_correlation_id(Some(self.correlation_id))
+            .set_timestamp_dt(finished)
+            .set_start_timestamp_dt(self.started)
+            .set_finish_timestamp_dt(finished)
+            .set_duration_in_ms(duration_in_ms);
+        event
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::Operation,
+        crate::{echo::Event, error::Result},
+        serde_json::Value,
+        std::{thread, time::Duration},
+    };
+
+    #[test]
+    fn start_and_finish() -> Result<()> {
+        let mut template = Event::default();
+        let _ = template.set_routing_key("atlas-dev-promises");
+
+        let operation = Operation::begin(&template);
+        let start = serde_json::to_value(operation.start_event())?;
+        thread::sleep(Duration::from_millis(5));
+        let finish = serde_json::to_value(operation.finish_event())?;
+
+        let correlation_id = Value::String(operation.correlation_id().to_string());
+        assert_eq!(start["correlationId"], correlation_id);
+        assert_eq!(finish["correlationId"], correlation_id);
+        assert_eq!(finish["routingKey"], "atlas-dev-promises");
+        assert_eq!(start["startTimestamp"], finish["startTimestamp"]);
+        assert!(finish["finishTimestamp"].as_u64() > start["timestamp"].as_u64());
+        assert!(finish["durationInMs"].as_u64() >= Some(5));
+        Ok(())
+    }
+}
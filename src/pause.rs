@@ -0,0 +1,163 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Pausing and resuming sends
+
+use {
+    crate::echo::Payload,
+    std::{collections::VecDeque, sync::Mutex},
+};
+
+/// The default number of payloads buffered while paused
+pub(crate) const DEFAULT_PAUSE_BUFFER_CAPACITY: usize = 1024;
+
+/// What to do with payloads spawned while a `Spawner` is paused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PausePolicy {
+    /// Hold the payloads in memory and send them on resume
+    Buffer,
+    /// Drop the payloads
+    Drop,
+}
+
+impl Default for PausePolicy {
+    fn default() -> Self {
+        Self::Buffer
+    }
+}
+
+/// The paused state of a `Spawner`, and the payloads buffered while paused
+#[derive(Debug, Default)]
+pub(crate) struct PauseGate {
+    /// `Some` with the buffered payloads while paused, `None` otherwise
+    state: Mutex<Option<VecDeque<Payload>>>,
+}
+
+impl PauseGate {
+    /// Pause the gate.  Pausing an already paused gate has no effect.
+    pub(crate) fn pause(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.is_none() {
+                *state = Some(VecDeque::new());
+            }
+        }
+    }
+
+    /// Resume the gate, returning the payloads buffered while paused
+    pub(crate) fn resume(&self) -> Vec<Payload> {
+        match self.state.lock() {
+            Ok(mut state) => state.take().map(Vec::from).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Whether the gate is paused
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.lock().map_or(false, |state| state.is_some())
+    }
+
+    /// Hold the payload, according to `policy`, if the gate is paused.  Once `capacity`
+    /// payloads are buffered, the oldest buffered payloads are dropped to make room.
+    ///
+    /// Returns the action taken and the number of events in any dropped payloads, or
+    /// `None` if the gate isn't paused and the payload should be sent.
+    pub(crate) fn hold(
+        &self,
+        payload: &Payload,
+        policy: PausePolicy,
+        capacity: Option<usize>,
+    ) -> Option<(PausePolicy, usize)> {
+        let mut state = self.state.lock().ok()?;
+        let buffered = state.as_mut()?;
+
+        if policy == PausePolicy::Drop || capacity == Some(0) {
+            return Some((PausePolicy::Drop, 0));
+        }
+
+        let mut evicted = 0;
+        if let Some(capacity) = capacity {
+            while buffered.len() >= capacity {
+                evicted += buffered
+                    .pop_front()
+                    .map_or(0, |oldest| oldest.event_count());
+            }
+        }
+        buffered.push_back(payload.clone());
+        Some((PausePolicy::Buffer, evicted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{PauseGate, PausePolicy},
+        crate::echo::{Event, Payload},
+    };
+
+    #[test]
+    fn pause_and_resume() {
+        let gate = PauseGate::default();
+        let payload = Payload::default();
+        assert!(!gate.is_paused());
+        assert_eq!(gate.hold(&payload, PausePolicy::Buffer, None), None);
+
+        gate.pause();
+        assert!(gate.is_paused());
+        assert_eq!(
+            gate.hold(&payload, PausePolicy::Buffer, None),
+            Some((PausePolicy::Buffer, 0))
+        );
+        assert_eq!(
+            gate.hold(&payload, PausePolicy::Drop, None),
+            Some((PausePolicy::Drop, 0))
+        );
+
+        assert_eq!(gate.resume().len(), 1);
+        assert!(!gate.is_paused());
+        assert!(gate.resume().is_empty());
+    }
+
+    #[test]
+    fn drop_oldest_at_capacity() {
+        let gate = PauseGate::default();
+        let payloads: Vec<Payload> = (0..3)
+            .map(|i| {
+                let mut payload = Payload::default();
+                let _ = payload.set_events(vec![Event::default(); i + 1]);
+                payload
+            })
+            .collect();
+
+        gate.pause();
+        assert_eq!(
+            gate.hold(&payloads[0], PausePolicy::Buffer, Some(2)),
+            Some((PausePolicy::Buffer, 0))
+        );
+        assert_eq!(
+            gate.hold(&payloads[1], PausePolicy::Buffer, Some(2)),
+            Some((PausePolicy::Buffer, 0))
+        );
+        assert_eq!(
+            gate.hold(&payloads[2], PausePolicy::Buffer, Some(2)),
+            Some((PausePolicy::Buffer, 1))
+        );
+        assert_eq!(
+            gate.hold(&payloads[2], PausePolicy::Buffer, Some(0)),
+            Some((PausePolicy::Drop, 0))
+        );
+
+        let buffered = gate.resume();
+        assert_eq!(
+            buffered
+                .iter()
+                .map(Payload::event_count)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+}
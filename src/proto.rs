@@ -0,0 +1,82 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Protobuf Event encoding, matching `proto/echo.proto`
+
+use {prost::Message, std::collections::HashMap};
+
+/// The protobuf `Content-Type`
+pub(crate) const CONTENT_TYPE: &str = "application/x-protobuf";
+
+/// The protobuf representation of an Echo `Event`
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ProtoEvent {
+    #[prost(string, required, tag = "1")]
+    pub(crate) routing_key: String,
+    #[prost(string, required, tag = "2")]
+    pub(crate) event_type: String,
+    #[prost(string, required, tag = "3")]
+    pub(crate) message: String,
+    #[prost(string, optional, tag = "4")]
+    pub(crate) correlation_id: Option<String>,
+    #[prost(int64, optional, tag = "5")]
+    pub(crate) timestamp: Option<i64>,
+    #[prost(map = "string, string", tag = "6")]
+    pub(crate) message_detail: HashMap<String, String>,
+    #[prost(string, optional, tag = "7")]
+    pub(crate) host: Option<String>,
+    #[prost(string, optional, tag = "8")]
+    pub(crate) application_version: Option<String>,
+    #[prost(string, optional, tag = "9")]
+    pub(crate) data_center: Option<String>,
+    #[prost(string, optional, tag = "10")]
+    pub(crate) client_host_name: Option<String>,
+    #[prost(string, optional, tag = "11")]
+    pub(crate) destination_host_name: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub(crate) destination_path: Option<String>,
+    #[prost(uint64, optional, tag = "13")]
+    pub(crate) start_timestamp: Option<u64>,
+    #[prost(uint64, optional, tag = "14")]
+    pub(crate) finish_timestamp: Option<u64>,
+    #[prost(uint64, optional, tag = "15")]
+    pub(crate) duration: Option<u64>,
+    #[prost(uint64, optional, tag = "16")]
+    pub(crate) duration_in_ms: Option<u64>,
+    #[prost(uint32, optional, tag = "17")]
+    pub(crate) response_code: Option<u32>,
+    #[prost(string, optional, tag = "18")]
+    pub(crate) response: Option<String>,
+    #[prost(uint64, optional, tag = "19")]
+    pub(crate) sequence: Option<u64>,
+    #[prost(string, optional, tag = "20")]
+    pub(crate) tenant_id: Option<String>,
+}
+
+/// The protobuf representation of a batch of Echo `Event`s
+#[derive(Clone, PartialEq, Message)]
+pub(crate) struct ProtoEventBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub(crate) events: Vec<ProtoEvent>,
+}
+
+/// Encode the batch of events
+pub(crate) fn encode(events: Vec<ProtoEvent>) -> crate::error::Result<Vec<u8>> {
+    let batch = ProtoEventBatch { events };
+    let mut buf = Vec::with_capacity(batch.encoded_len());
+    batch.encode(&mut buf).map_err(|e| format!("{}", e))?;
+    Ok(buf)
+}
+
+/// Decode a batch of events
+#[cfg(test)]
+pub(crate) fn decode(buf: &[u8]) -> crate::error::Result<Vec<ProtoEvent>> {
+    Ok(ProtoEventBatch::decode(buf)
+        .map_err(|e| format!("{}", e))?
+        .events)
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Event sampling
+
+use {crate::echo::EventType, std::collections::HashMap};
+
+/// The fraction of each event type sent, for shedding high-volume events under load.
+///
+/// Each event is kept with the probability of its type's rate, from `0.0` (always
+/// dropped) to `1.0` (always sent).  Types without a rate are always sent, so `ERROR`
+/// and `PERFORMANCE` events go through unless explicitly sampled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SamplingPolicy {
+    /// The sample rate of each event type
+    rates: HashMap<EventType, f64>,
+}
+
+impl SamplingPolicy {
+    /// Create a new policy, sending every event
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the fraction of events of the given type sent, clamped to `0.0..=1.0`
+    pub fn set_rate(&mut self, event_type: EventType, rate: f64) -> &mut Self {
+        let rate = if rate.is_nan() {
+            1.0
+        } else {
+            rate.max(0.0).min(1.0)
+        };
+        let _ = self.rates.insert(event_type, rate);
+        self
+    }
+
+    /// The fraction of events of the given type sent
+    #[must_use]
+    pub fn rate(&self, event_type: &EventType) -> f64 {
+        self.rates.get(event_type).copied().unwrap_or(1.0)
+    }
+
+    /// Roll whether to keep an event of the given type
+    pub(crate) fn keep(&self, event_type: &EventType) -> bool {
+        let rate = self.rate(event_type);
+        rate >= 1.0 || (rate > 0.0 && rand::random::<f64>() < rate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::SamplingPolicy, crate::echo::EventType};
+
+    #[test]
+    fn rates() {
+        let mut policy = SamplingPolicy::new();
+        let _ = policy
+            .set_rate(EventType::Info, 0.0)
+            .set_rate(EventType::Tracking, 2.0)
+            .set_rate(EventType::System, f64::NAN);
+
+        assert!((policy.rate(&EventType::Error) - 1.0).abs() < f64::EPSILON);
+        assert!((policy.rate(&EventType::Tracking) - 1.0).abs() < f64::EPSILON);
+        assert!((policy.rate(&EventType::System) - 1.0).abs() < f64::EPSILON);
+        assert!(policy.rate(&EventType::Info).abs() < f64::EPSILON);
+
+        for _ in 0..100 {
+            assert!(!policy.keep(&EventType::Info));
+            assert!(policy.keep(&EventType::Error));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! The `spawn`/`send` surface, for injecting a `MockSpawner` in tests
+
+use crate::echo::{Payload, Spawner};
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::{Arc, Mutex};
+
+/// Something payloads can be spawned or sent to.  Write your code against this trait
+/// to inject a `MockSpawner` (`test-util` feature) in your tests in place of the real
+/// `Spawner`.
+pub trait EchoSink {
+    /// Spawn a payload without waiting for the result, see `Spawner::spawn`
+    ///
+    /// # Errors
+    ///
+    fn spawn(&self, payload: &Payload) -> crate::error::Result<()>;
+
+    /// Send a payload, waiting for the result, see `Spawner::send`
+    ///
+    /// # Errors
+    ///
+    fn send(&self, payload: &Payload) -> crate::error::Result<()>;
+}
+
+impl EchoSink for Spawner {
+    fn spawn(&self, payload: &Payload) -> crate::error::Result<()> {
+        Self::spawn(self, payload)
+    }
+
+    fn send(&self, payload: &Payload) -> crate::error::Result<()> {
+        Self::send(self, payload)
+    }
+}
+
+/// An `EchoSink` that records the JSON body of every payload in memory, without any
+/// network, for asserting what your code submitted.  Clones share the recorded bodies.
+///
+/// The bodies are the raw `Payload::serialize_events` output.  None of the filtering
+/// or enrichment a `Spawner` applies before sending is done, i.e. sampling, minimum
+/// severity, deduplication, timestamp and correlation id stamping, tenant id, data
+/// center and host defaults, or field casing, so assert on what your code built rather
+/// than on what the collector would receive.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Clone, Debug, Default)]
+pub struct MockSpawner {
+    /// The recorded bodies
+    sent: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockSpawner {
+    /// Create a new mock with nothing recorded
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The JSON array bodies recorded so far, in the order they were submitted
+    #[must_use]
+    pub fn sent(&self) -> Vec<String> {
+        self.sent
+            .lock()
+            .map(|sent| sent.clone())
+            .unwrap_or_default()
+    }
+
+    /// Record the payload body, skipping payloads without events like the `Spawner`
+    fn record(&self, payload: &Payload) -> crate::error::Result<()> {
+        if payload.event_count() == 0 {
+            return Ok(());
+        }
+
+        let (json, mut errors) = payload.serialize_events();
+
+        if !errors.is_empty() {
+            return Err(errors.remove(0).1);
+        }
+        self.sent
+            .lock()
+            .map_err(|_| "the recorded bodies are poisoned")?
+            .push(json);
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl EchoSink for MockSpawner {
+    fn spawn(&self, payload: &Payload) -> crate::error::Result<()> {
+        self.record(payload)
+    }
+
+    fn send(&self, payload: &Payload) -> crate::error::Result<()> {
+        self.record(payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::EchoSink,
+        crate::{
+            echo::{CollectorUrl, Event, Payload, Spawner},
+            error::Result,
+        },
+    };
+
+    fn emit(sink: &dyn EchoSink, message: &str) -> Result<()> {
+        let mut echo_event = Event::default();
+        let _ = echo_event
+            .set_routing_key("atlas-dev-promises")
+            .set_message(message);
+        let mut payload = Payload::default();
+        let _ = payload
+            .set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".to_string()))
+            .add_event(echo_event);
+        sink.spawn(&payload)
+    }
+
+    #[test]
+    fn spawner_sink() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        emit(&echo_spawner, "spawned")?;
+        assert!(echo_spawner
+            .metrics_text()
+            .contains(r#"routing_key="atlas-dev-promises"} 1"#));
+        Ok(())
+    }
+
+    #[test]
+    fn mock_spawner() -> Result<()> {
+        let mock = super::MockSpawner::new();
+        emit(&mock, "first")?;
+        emit(&mock.clone(), "second")?;
+        mock.send(&Payload::default())?;
+
+        let sent = mock.sent();
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].contains(r#""message":"first""#));
+        assert!(sent[1].contains(r#""message":"second""#));
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Spawner session statistics
+
+use {
+    crate::{body::BodySize, echo::CollectorUrl},
+    std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU64, Ordering},
+        time::Duration,
+    },
+};
+
+/// Counters shared between a `Spawner` and its spawned sends
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    /// The number of events successfully sent
+    pub(crate) sent: AtomicU64,
+    /// The number of payloads that failed to send
+    pub(crate) failed: AtomicU64,
+    /// The number of payload send retries
+    pub(crate) retried: AtomicU64,
+    /// The number of events dropped by the `min_severity` filter
+    pub(crate) filtered: AtomicU64,
+    /// The number of events dropped by the `sampling` policy
+    pub(crate) sampled: AtomicU64,
+    /// The number of events buffered while paused
+    pub(crate) paused_buffered: AtomicU64,
+    /// The number of events dropped while paused, including those dropped from a full
+    /// pause buffer
+    pub(crate) paused_dropped: AtomicU64,
+    /// The uncompressed bytes successfully sent
+    pub(crate) bytes_uncompressed: AtomicU64,
+    /// The bytes successfully sent on the wire, after any compression
+    pub(crate) bytes_compressed: AtomicU64,
+}
+
+impl Stats {
+    /// Add `count` to the given counter
+    pub(crate) fn add(counter: &AtomicU64, count: usize) {
+        let _ = counter.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Record a successfully sent body
+    pub(crate) fn add_bytes(&self, size: BodySize) {
+        Self::add(&self.bytes_uncompressed, size.uncompressed);
+        Self::add(&self.bytes_compressed, size.encoded);
+    }
+
+    /// The bytes sent so far
+    pub(crate) fn bytes_sent(&self) -> BytesSent {
+        BytesSent {
+            uncompressed: self.bytes_uncompressed.load(Ordering::Relaxed),
+            compressed: self.bytes_compressed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Summarize the counters, plus the session uptime, as `message_detail` entries
+    pub(crate) fn summary(&self, uptime: Duration) -> HashMap<String, String> {
+        let mut detail = HashMap::new();
+        let counters = [
+            ("sent", &self.sent),
+            ("failed", &self.failed),
+            ("retried", &self.retried),
+            ("filtered", &self.filtered),
+            ("sampled", &self.sampled),
+            ("paused_buffered", &self.paused_buffered),
+            ("paused_dropped", &self.paused_dropped),
+        ];
+
+        for (key, counter) in &counters {
+            let _ = detail.insert(
+                (*key).to_string(),
+                counter.load(Ordering::Relaxed).to_string(),
+            );
+        }
+        let _ = detail.insert("uptime_ms".to_string(), uptime.as_millis().to_string());
+        detail
+    }
+}
+
+/// The total bytes successfully sent to the collector
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BytesSent {
+    /// The bytes of serialized JSON
+    uncompressed: u64,
+    /// The bytes on the wire, after any compression
+    compressed: u64,
+}
+
+impl BytesSent {
+    /// The bytes of serialized JSON
+    #[must_use]
+    pub fn uncompressed(self) -> u64 {
+        self.uncompressed
+    }
+
+    /// The bytes on the wire, after any compression.  This equals `uncompressed` when
+    /// compression is disabled.
+    #[must_use]
+    pub fn compressed(self) -> u64 {
+        self.compressed
+    }
+}
+
+/// Where to send the SYSTEM event summarizing the session when a `Spawner` shuts down
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShutdownSummary {
+    /// The collector url the summary is sent to
+    pub(crate) url: CollectorUrl,
+    /// The routing key of the summary event
+    pub(crate) routing_key: String,
+}
+
+impl ShutdownSummary {
+    /// Create a new `ShutdownSummary` sent to `url` with the given routing key
+    pub fn new<T>(url: CollectorUrl, routing_key: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            url,
+            routing_key: routing_key.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::Stats, crate::body::BodySize, std::time::Duration};
+
+    #[test]
+    fn summary() {
+        let stats = Stats::default();
+        Stats::add(&stats.sent, 10);
+        Stats::add(&stats.failed, 1);
+
+        let summary = stats.summary(Duration::from_millis(1500));
+        assert_eq!(summary["sent"], "10");
+        assert_eq!(summary["failed"], "1");
+        assert_eq!(summary["retried"], "0");
+        assert_eq!(summary["filtered"], "0");
+        assert_eq!(summary["sampled"], "0");
+        assert_eq!(summary["paused_dropped"], "0");
+        assert_eq!(summary["uptime_ms"], "1500");
+    }
+
+    #[test]
+    fn bytes_sent() {
+        let stats = Stats::default();
+        stats.add_bytes(BodySize {
+            uncompressed: 100,
+            encoded: 40,
+        });
+        stats.add_bytes(BodySize {
+            uncompressed: 10,
+            encoded: 10,
+        });
+
+        let bytes_sent = stats.bytes_sent();
+        assert_eq!(bytes_sent.uncompressed(), 110);
+        assert_eq!(bytes_sent.compressed(), 50);
+    }
+}
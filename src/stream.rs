@@ -0,0 +1,143 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Stream based event submission
+
+use {
+    crate::echo::{Event, Payload, Spawner},
+    futures::{pin_mut, Stream, StreamExt},
+    std::{future::Future, mem, time::Duration},
+    tokio::time::{timeout_at, Instant},
+};
+
+/// The shortest `flush_interval`, so a zero interval can't spin flushing empty batches
+const MIN_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Batching configuration for `Spawner::consume_stream` and `BatchingSpawner`
+#[derive(Clone, Debug)]
+pub struct StreamConfig {
+    /// The payload template (url, logger, etc.) each batch is sent with
+    pub(crate) payload: Payload,
+    /// The number of events that triggers a flush
+    pub(crate) max_batch_size: usize,
+    /// The maximum time a partial batch is held before it is flushed
+    pub(crate) flush_interval: Duration,
+}
+
+impl StreamConfig {
+    /// Create a new `StreamConfig`.  Each batch is sent as a clone of `payload` with its
+    /// events replaced by the batch.  The `flush_interval` is at least 10ms.
+    #[must_use]
+    pub fn new(payload: Payload, max_batch_size: usize, flush_interval: Duration) -> Self {
+        Self {
+            payload,
+            max_batch_size: max_batch_size.max(1),
+            flush_interval: flush_interval.max(MIN_FLUSH_INTERVAL),
+        }
+    }
+}
+
+impl Spawner {
+    /// Consume a stream of events, spawning them in batches of up to `max_batch_size`
+    /// events.  A partial batch is flushed when `flush_interval` elapses, and the final
+    /// partial batch is flushed when the stream completes.
+    ///
+    /// The returned future must be driven within a `tokio` runtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error spawning a batch.
+    pub fn consume_stream<'a, S>(
+        &'a self,
+        stream: S,
+        config: StreamConfig,
+    ) -> impl Future<Output = crate::error::Result<()>> + 'a
+    where
+        S: Stream<Item = Event> + 'a,
+    {
+        async move {
+            pin_mut!(stream);
+            let mut batch = Vec::with_capacity(config.max_batch_size);
+            let mut deadline = Instant::now() + config.flush_interval;
+
+            loop {
+                match timeout_at(deadline, stream.next()).await {
+                    Ok(Some(event)) => {
+                        batch.push(event);
+
+                        if batch.len() >= config.max_batch_size {
+                            self.spawn_batch(&config, &mut batch)?;
+                            deadline = Instant::now() + config.flush_interval;
+                        }
+                    }
+                    Ok(None) => return self.spawn_batch(&config, &mut batch),
+                    Err(_) => {
+                        self.spawn_batch(&config, &mut batch)?;
+                        deadline = Instant::now() + config.flush_interval;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spawn the batched events as a clone of the configured payload, leaving the batch
+    /// empty
+    pub(crate) fn spawn_batch(
+        &self,
+        config: &StreamConfig,
+        batch: &mut Vec<Event>,
+    ) -> crate::error::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut payload = config.payload.clone();
+        let _ = payload.set_events(mem::replace(
+            batch,
+            Vec::with_capacity(config.max_batch_size),
+        ));
+        self.spawn(&payload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::StreamConfig,
+        crate::{
+            echo::{CollectorUrl, Event, Payload, Spawner},
+            error::Result,
+        },
+        futures::stream,
+        std::time::Duration,
+        tokio::runtime::Runtime,
+    };
+
+    #[test]
+    fn consume_stream() -> Result<()> {
+        let echo_spawner = Spawner::new()?;
+        let mut rt = Runtime::new()?;
+
+        let events = (0..5).map(|i| {
+            let mut echo_event = Event::default();
+            let _ = echo_event
+                .set_routing_key("atlas-dev-promises")
+                .set_message(format!("Message {}", i));
+            echo_event
+        });
+        let mut template = Payload::default();
+        let _ = template.set_url(CollectorUrl::Custom("http://127.0.0.1:1/echo".into()));
+        let config = StreamConfig::new(template, 2, Duration::from_secs(1));
+
+        rt.block_on(echo_spawner.consume_stream(stream::iter(events), config))?;
+        assert!(echo_spawner
+            .metrics_text()
+            .contains(r#"echo_events_total{type="INFO",routing_key="atlas-dev-promises"} 5"#));
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Event timestamps
+
+use {
+    chrono::{DateTime, SecondsFormat, TimeZone, Utc},
+    serde::{
+        de::{self, Deserialize, Deserializer},
+        ser::{Serialize, Serializer},
+    },
+    serde_derive::Deserialize,
+};
+
+/// An event timestamp in milliseconds since the epoch, and the form it's serialized in
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub(crate) enum Timestamp {
+    /// Serialized as a number of milliseconds (the default)
+    Millis(i64),
+    /// Serialized as an RFC 3339 (ISO-8601) string, with millisecond precision
+    Rfc3339(i64),
+}
+
+impl Timestamp {
+    /// The milliseconds since the epoch
+    pub(crate) fn millis(self) -> i64 {
+        match self {
+            Self::Millis(millis) | Self::Rfc3339(millis) => millis,
+        }
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Rfc3339(millis) => match Utc.timestamp_millis_opt(*millis).single() {
+                Some(dt) => {
+                    serializer.serialize_str(&dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+                }
+                None => serializer.serialize_i64(*millis),
+            },
+            Self::Millis(millis) => serializer.serialize_i64(*millis),
+        }
+    }
+}
+
+/// The timestamp forms accepted by the collector
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Wire {
+    /// Milliseconds since the epoch
+    Millis(i64),
+    /// An ISO-8601 date/time string
+    Iso8601(String),
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Wire::deserialize(deserializer)? {
+            Wire::Millis(millis) => Ok(Self::Millis(millis)),
+            Wire::Iso8601(iso) => DateTime::parse_from_rfc3339(&iso)
+                .map(|dt| Self::Rfc3339(dt.timestamp_millis()))
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::Timestamp, crate::error::Result};
+
+    #[test]
+    fn serialize() -> Result<()> {
+        assert_eq!(
+            serde_json::to_string(&Timestamp::Millis(196_300_801_666))?,
+            "196300801666"
+        );
+        assert_eq!(
+            serde_json::to_string(&Timestamp::Rfc3339(196_300_801_666))?,
+            r#""1976-03-22T00:00:01.666Z""#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize() -> Result<()> {
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("196300801666")?,
+            Timestamp::Millis(196_300_801_666)
+        );
+        assert_eq!(
+            serde_json::from_str::<Timestamp>(r#""1976-03-21T20:00:01.666-04:00""#)?,
+            Timestamp::Rfc3339(196_300_801_666)
+        );
+        assert!(serde_json::from_str::<Timestamp>(r#""yesterday""#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! The shared HTTP(S) client, with either the `tls` (`native-tls`) or `rustls` backend
+
+use {
+    crate::{
+        config::{ClientIdentity, SpawnerConfig},
+        dns::LimitedResolver,
+    },
+    hyper::{client::HttpConnector, Body, Client},
+    hyper_proxy::ProxyConnector,
+};
+
+#[cfg(feature = "tls")]
+use native_tls::{Identity, TlsConnector, TlsConnectorBuilder};
+
+#[cfg(feature = "rustls")]
+use rustls_crate::{internal::pemfile, ClientConfig};
+
+/// The HTTPS connector of the enabled TLS backend
+#[cfg(feature = "tls")]
+type HttpsConnector = hyper_tls::HttpsConnector<HttpConnector<LimitedResolver>>;
+
+/// The HTTPS connector of the enabled TLS backend
+#[cfg(feature = "rustls")]
+type HttpsConnector = hyper_rustls::HttpsConnector<HttpConnector<LimitedResolver>>;
+
+/// The shared HTTP(S) client type
+pub(crate) type HttpsClient = Client<ProxyConnector<HttpsConnector>>;
+
+/// Build the shared HTTP(S) client, presenting the configured client certificate, if
+/// any
+pub(crate) fn client(config: &SpawnerConfig) -> crate::error::Result<HttpsClient> {
+    match config.identity() {
+        #[cfg(feature = "tls")]
+        Some(ClientIdentity::Pkcs12(pkcs12_der, password)) => {
+            client_with_identity(config, pkcs12_der, &password.0)
+        }
+        Some(ClientIdentity::Pem(cert_chain_pem, key_pem)) => {
+            client_with_pem_identity(config, cert_chain_pem, &key_pem.0)
+        }
+        None => {
+            #[cfg(feature = "tls")]
+            {
+                native_client(TlsConnector::builder(), config)
+            }
+            #[cfg(feature = "rustls")]
+            {
+                rustls_client(rustls_config(), config)
+            }
+        }
+    }
+}
+
+/// Build the shared HTTP(S) client, presenting the client certificate in the PKCS #12
+/// archive for mutual TLS
+#[cfg(feature = "tls")]
+fn client_with_identity(
+    config: &SpawnerConfig,
+    pkcs12_der: &[u8],
+    password: &str,
+) -> crate::error::Result<HttpsClient> {
+    let mut tls_builder = TlsConnector::builder();
+    let _ = tls_builder.identity(Identity::from_pkcs12(pkcs12_der, password)?);
+    native_client(tls_builder, config)
+}
+
+/// Build the shared HTTP(S) client, presenting the PEM encoded client certificate
+/// chain and PKCS #8 private key for mutual TLS
+fn client_with_pem_identity(
+    config: &SpawnerConfig,
+    cert_chain_pem: &[u8],
+    key_pem: &[u8],
+) -> crate::error::Result<HttpsClient> {
+    #[cfg(feature = "tls")]
+    {
+        let mut tls_builder = TlsConnector::builder();
+        let _ = tls_builder.identity(Identity::from_pkcs8(cert_chain_pem, key_pem)?);
+        native_client(tls_builder, config)
+    }
+    #[cfg(feature = "rustls")]
+    {
+        let certs = pemfile::certs(&mut &cert_chain_pem[..])
+            .map_err(|_| "invalid PEM client certificate chain")?;
+        let key = pemfile::pkcs8_private_keys(&mut &key_pem[..])
+            .map_err(|_| "invalid PEM client private key")?
+            .pop()
+            .ok_or("no PKCS #8 client private key")?;
+        let mut tls = rustls_config();
+        tls.set_single_client_cert(certs, key)
+            .map_err(|e| e.to_string())?;
+        rustls_client(tls, config)
+    }
+}
+
+#[cfg(feature = "tls")]
+fn native_client(
+    mut tls_builder: TlsConnectorBuilder,
+    config: &SpawnerConfig,
+) -> crate::error::Result<HttpsClient> {
+    let _ = tls_builder.danger_accept_invalid_certs(config.accept_invalid_certs());
+
+    // `hyper-tls` doesn't tell `hyper` which protocol ALPN negotiated, so h2 is only
+    // offered when `hyper` speaks HTTP/2 regardless
+    let http2 = config.http2_prior_knowledge() || config.http2_only();
+
+    if http2 {
+        let _ = tls_builder.request_alpns(&["h2"]);
+    }
+    let tls = tls_builder.build()?;
+
+    let https = hyper_tls::HttpsConnector::from((http_connector(config), tls.clone().into()));
+    let mut connector = ProxyConnector::unsecured(https);
+
+    if let Some(proxy) = config.resolve_proxy()? {
+        // Tunneled https requests are wrapped in TLS by the proxy connector
+        connector.set_tls(Some(tls));
+        connector.add_proxy(proxy);
+    }
+
+    Ok(build(connector, config, http2))
+}
+
+/// The `rustls` configuration, verifying the collector against the webpki roots
+#[cfg(feature = "rustls")]
+fn rustls_config() -> ClientConfig {
+    let mut tls = ClientConfig::new();
+    tls.root_store
+        .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    tls
+}
+
+#[cfg(feature = "rustls")]
+fn rustls_client(
+    mut tls: ClientConfig,
+    config: &SpawnerConfig,
+) -> crate::error::Result<HttpsClient> {
+    tls.set_protocols(&if config.http2_prior_knowledge() || config.http2_only() {
+        vec![b"h2".to_vec()]
+    } else {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    });
+
+    let https = hyper_rustls::HttpsConnector::from((http_connector(config), tls));
+    let connector = match config.resolve_proxy()? {
+        // Tunneled https requests are wrapped in TLS by the proxy connector
+        Some(proxy) => ProxyConnector::from_proxy(https, proxy)?,
+        None => ProxyConnector::unsecured(https),
+    };
+
+    Ok(build(connector, config, config.http2_prior_knowledge()))
+}
+
+/// The plain HTTP connector the TLS backends wrap
+fn http_connector(config: &SpawnerConfig) -> HttpConnector<LimitedResolver> {
+    let mut http = HttpConnector::new_with_resolver(LimitedResolver::new(config.dns_threads()));
+    http.enforce_http(false);
+    http
+}
+
+/// Build the client, speaking HTTP/2 without negotiating it if `http2_only` is set
+fn build(
+    connector: ProxyConnector<HttpsConnector>,
+    config: &SpawnerConfig,
+    http2_only: bool,
+) -> HttpsClient {
+    Client::builder()
+        .pool_max_idle_per_host(config.pool_max_idle_per_host())
+        .pool_idle_timeout(config.pool_idle_timeout())
+        .http2_only(http2_only)
+        .build::<_, Body>(connector)
+}
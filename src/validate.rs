@@ -0,0 +1,67 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Event Validation
+
+use {
+    slog::{warn, Logger},
+    slog_try::try_warn,
+};
+
+/// How strictly a validation check is enforced at spawn time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strictness {
+    /// Skip the check
+    Ignore,
+    /// Log a warning through the payload logger and send anyway
+    Warn,
+    /// Fail the spawn with the validation error
+    Error,
+}
+
+impl Default for Strictness {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// What to do with invalid events in an otherwise valid batch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvalidEventPolicy {
+    /// Fail the whole batch
+    Fail,
+    /// Drop the invalid events, logging them, and send the rest
+    Drop,
+}
+
+impl Default for InvalidEventPolicy {
+    fn default() -> Self {
+        Self::Fail
+    }
+}
+
+impl Strictness {
+    /// Apply this strictness to the result of a validation check on the event at `idx`
+    pub(crate) fn enforce(
+        self,
+        logger: &Option<Logger>,
+        idx: usize,
+        check: impl FnOnce() -> crate::error::Result<()>,
+    ) -> crate::error::Result<()> {
+        match self {
+            Self::Ignore => Ok(()),
+            Self::Warn => {
+                if let Err(e) = check() {
+                    try_warn!(logger, "Event {} failed validation: {:?}", idx, e);
+                }
+                Ok(())
+            }
+            Self::Error => check(),
+        }
+    }
+}
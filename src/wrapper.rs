@@ -0,0 +1,86 @@
+// Copyright (c) 2019 libechoexec developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! JSON root wrapper
+
+use {getset::Setters, std::collections::BTreeMap};
+
+/// Wraps the serialized events array in a root object, i.e.
+/// `{"events":[...],"meta":{...}}`, for collectors expecting a wrapped schema.
+///
+/// The stage and prod collectors expect the bare array, so no wrapper is the default.
+#[derive(Clone, Debug, PartialEq, Setters)]
+pub struct RootWrapper {
+    /// The root key the events array is stored under
+    key: String,
+    /// Optional static metadata stored under the `meta` key
+    #[set = "pub"]
+    meta: Option<BTreeMap<String, String>>,
+}
+
+impl RootWrapper {
+    /// Create a new `RootWrapper` storing the events under `key`
+    pub fn new<T>(key: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            key: key.into(),
+            meta: None,
+        }
+    }
+
+    /// Wrap the serialized events array
+    pub(crate) fn wrap(&self, events: &str) -> crate::error::Result<String> {
+        let key = serde_json::to_string(&self.key)?;
+
+        Ok(match &self.meta {
+            Some(meta) => format!(
+                "{{{}:{},\"meta\":{}}}",
+                key,
+                events,
+                serde_json::to_string(meta)?
+            ),
+            None => format!("{{{}:{}}}", key, events),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::RootWrapper,
+        crate::error::Result,
+        serde_json::{json, Value},
+        std::collections::BTreeMap,
+    };
+
+    #[test]
+    fn wrap() -> Result<()> {
+        let events = r#"[{"routingKey":"","type":"INFO","message":""}]"#;
+        let mut wrapper = RootWrapper::new("events");
+        assert_eq!(
+            wrapper.wrap(events)?,
+            r#"{"events":[{"routingKey":"","type":"INFO","message":""}]}"#
+        );
+
+        let mut meta = BTreeMap::new();
+        let _ = meta.insert("source".to_string(), "libechoexec".to_string());
+        let _ = wrapper.set_meta(Some(meta));
+
+        let wrapped: Value = serde_json::from_str(&wrapper.wrap(events)?)?;
+        assert_eq!(
+            wrapped,
+            json!({
+                "events": [{"routingKey": "", "type": "INFO", "message": ""}],
+                "meta": {"source": "libechoexec"}
+            })
+        );
+        Ok(())
+    }
+}